@@ -0,0 +1,649 @@
+use crate::runner::TaskOutcome;
+use crate::script::{shell_quote, Task};
+use crate::sys;
+use log::debug;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashSet};
+use std::io;
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Where the tasks are run: builds the command running a task, the runner then redirects
+/// its outputs to the output files, spawns it and kills it after its timeout
+pub trait Executor: Send + Sync {
+    /// the command running task, with its address space limited to mem_limit_mb MB if given,
+    /// outputs are the files its stdout and stderr are redirected to: `<name>.out`,
+    /// or `<name>.stdout` and `<name>.stderr`
+    fn command(&self, task: &Task, mem_limit_mb: Option<u64>, outputs: &[PathBuf]) -> Command;
+
+    /// kill child, spawned from the command of task, after its timeout or when the run is stopped
+    fn kill(&self, _task: &Task, child: &mut Child) -> io::Result<()> {
+        child.kill()
+    }
+
+    /// the batch system the command of a task submits it to, `None` (the default) if the command
+    /// runs the task itself
+    fn batch(&self) -> Option<&dyn BatchSystem> {
+        None
+    }
+}
+
+/// A batch system the tasks are submitted to as jobs, e.g. SLURM: the command of a task only
+/// submits its job and prints the id of the job, which is then followed until it ends,
+/// the job writes the outputs of the task itself
+pub trait BatchSystem: Send + Sync {
+    /// the id of the job in the stdout of its submission, `None` if there is none
+    fn job_id(&self, submission: &str) -> Option<String>;
+
+    /// how the job ended, `None` while it is queued or running
+    fn job_end(&self, job_id: &str) -> io::Result<Option<JobEnd>>;
+
+    /// cancel the job, after its timeout or when the run is stopped
+    fn cancel(&self, job_id: &str) -> io::Result<()>;
+
+    /// the delay between two `job_end` of a job, not to overload the batch system
+    fn poll_interval(&self) -> Duration;
+}
+
+/// How a job ended, as reported by its batch system
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JobEnd {
+    pub outcome: TaskOutcome,
+    /// the final state of the job when it tells more than the outcome, e.g. `OUT_OF_MEMORY`
+    pub note: Option<String>,
+}
+
+/// Run the tasks as child processes of zexp
+#[derive(Debug, Default)]
+pub struct LocalExecutor {
+    /// niceness of the tasks, `None` to keep the one of zexp
+    nice: Option<i32>,
+    /// the cores the workers are pinned to, the tasks of the n-th worker are run on the
+    /// n-th core (modulo their number), on any core if empty
+    cores: Vec<usize>,
+}
+
+impl LocalExecutor {
+    pub fn new(nice: Option<i32>, cores: Vec<usize>) -> Self {
+        Self { nice, cores }
+    }
+}
+
+impl Executor for LocalExecutor {
+    fn command(&self, task: &Task, mem_limit_mb: Option<u64>, _outputs: &[PathBuf]) -> Command {
+        let mut command = Command::new(&task.cmd);
+        command.args(&task.args).envs(task.env.iter().cloned());
+        if let Some(cwd) = &task.cwd {
+            command.current_dir(cwd);
+        }
+        if let Some(mem_limit_mb) = mem_limit_mb {
+            sys::set_mem_limit(&mut command, mem_limit_mb);
+        }
+        if let Some(nice) = self.nice {
+            sys::set_nice(&mut command, nice);
+        }
+        if !self.cores.is_empty() {
+            // the hooks are not run by a worker, they are run on the first core
+            let worker = rayon::current_thread_index().unwrap_or(0);
+            sys::set_cpu_affinity(&mut command, self.cores[worker % self.cores.len()]);
+        }
+        command
+    }
+}
+
+/// Run the tasks on remote hosts with `ssh <host> -- <command line>`, taking the hosts
+/// in turn, the paths of the task (program and working directory) are the ones of the host
+///
+/// killing a task after its timeout kills its ssh client, the remote command gets no signal
+/// and may keep running until it writes to its closed outputs
+#[derive(Debug)]
+pub struct SshExecutor {
+    hosts: Vec<String>,
+    /// index of the host of the next task, modulo the number of hosts
+    next: AtomicUsize,
+}
+
+impl SshExecutor {
+    /// an executor over hosts, which must not be empty
+    pub fn new(hosts: Vec<String>) -> Self {
+        assert!(!hosts.is_empty(), "no host to run the tasks on");
+        Self {
+            hosts,
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    /// the command line run by the shell of the host: the one of the task, from its
+    /// working directory and with its memory limit
+    fn remote_command_line(task: &Task, mem_limit_mb: Option<u64>) -> String {
+        let mut line = String::new();
+        if let Some(mem_limit_mb) = mem_limit_mb {
+            line += &format!("ulimit -v {} && ", mem_limit_mb.saturating_mul(1024));
+        }
+        if let Some(cwd) = &task.cwd {
+            line += &format!("cd {} && ", shell_quote(&cwd.to_string_lossy()));
+        }
+        // `exec` so that the shell does not stay between sshd and the task
+        line + &task.exec_line()
+    }
+}
+
+impl Executor for SshExecutor {
+    fn command(&self, task: &Task, mem_limit_mb: Option<u64>, _outputs: &[PathBuf]) -> Command {
+        let host = &self.hosts[self.next.fetch_add(1, Ordering::Relaxed) % self.hosts.len()];
+        debug!("Task {} run on {}", task.name, host);
+        let mut command = Command::new("ssh");
+        // fail instead of waiting for a password
+        command
+            .args(["-o", "BatchMode=yes", host, "--"])
+            .arg(Self::remote_command_line(task, mem_limit_mb));
+        command
+    }
+}
+
+/// The DOCKER table
+#[derive(Debug, Clone, Deserialize)]
+pub struct DockerConfig {
+    /// the image the tasks are run in
+    pub image: String,
+    /// other volumes mounted in the containers, as given to `docker run -v`, e.g. "/data:/data"
+    #[serde(default)]
+    pub volumes: Vec<String>,
+}
+
+/// Run each task in a new container of an image, with `docker run --rm`, its working directory
+/// (the one of zexp by default) being mounted at the same path
+#[derive(Debug)]
+pub struct DockerExecutor {
+    config: DockerConfig,
+}
+
+impl DockerExecutor {
+    pub fn new(config: DockerConfig) -> Self {
+        Self { config }
+    }
+
+    /// the name of the container of task, unique among the runs of zexp on the host as long as
+    /// the tasks are not run twice at once
+    fn container_name(task: &Task) -> String {
+        let name: String = task
+            .name
+            .chars()
+            .map(|c| match c {
+                'a'..='z' | 'A'..='Z' | '0'..='9' | '_' | '.' | '-' => c,
+                _ => '_',
+            })
+            .collect();
+        format!("zexp-{}-{}", std::process::id(), name)
+    }
+}
+
+impl Executor for DockerExecutor {
+    fn command(&self, task: &Task, mem_limit_mb: Option<u64>, _outputs: &[PathBuf]) -> Command {
+        let cwd = match &task.cwd {
+            Some(cwd) => cwd.clone(),
+            None => std::env::current_dir().unwrap_or_else(|_| PathBuf::from("/")),
+        };
+        let cwd = cwd.to_string_lossy();
+        let mut command = Command::new("docker");
+        command
+            .args(["run", "--rm", "--name", &Self::container_name(task)])
+            .args(["-v", &format!("{}:{}", cwd, cwd), "-w", &cwd]);
+        for volume in &self.config.volumes {
+            command.args(["-v", volume]);
+        }
+        for (key, value) in &task.env {
+            command.args(["-e", &format!("{}={}", key, value)]);
+        }
+        if let Some(mem_limit_mb) = mem_limit_mb {
+            command.arg(format!("--memory={}m", mem_limit_mb));
+        }
+        command
+            .arg(&self.config.image)
+            .arg(&task.cmd)
+            .args(&task.args);
+        command
+    }
+
+    /// kill the container, killing the docker client would leave it running
+    fn kill(&self, task: &Task, child: &mut Child) -> io::Result<()> {
+        let killed = Command::new("docker")
+            .args(["kill", &Self::container_name(task)])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status();
+        match killed {
+            Ok(status) if status.success() => {}
+            Ok(status) => debug!("docker kill of task {} exited with {}", task.name, status),
+            Err(e) => debug!("docker kill of task {} failed: {}", task.name, e),
+        }
+        child.kill()
+    }
+}
+
+/// The SLURM table, the resources of each job, as given to sbatch
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct SlurmConfig {
+    pub partition: Option<String>,
+    /// time limit, e.g. "02:00:00"
+    pub time: Option<String>,
+    /// memory, e.g. "4G", the memory limit of the task if it has one otherwise
+    pub mem: Option<String>,
+    pub cpus: Option<u32>,
+}
+
+/// Submit each task as a job with `sbatch`, then follow the job with `squeue` until it leaves
+/// the queue, and get how it ended with `sacct`, which needs the accounting of SLURM
+///
+/// the job writes its outputs to the output files of the task, which must then be on a file
+/// system shared with the nodes
+#[derive(Debug)]
+pub struct SlurmExecutor {
+    config: SlurmConfig,
+    queue: Mutex<JobQueue>,
+}
+
+/// The jobs submitted by a `SlurmExecutor`, the queue is listed once for all of them
+#[derive(Debug, Default)]
+struct JobQueue {
+    /// the jobs not known to be ended, with when they were submitted
+    jobs: BTreeMap<String, Instant>,
+    /// the jobs in the queue at the last listing, and when it was done
+    listed: HashSet<String>,
+    listed_at: Option<Instant>,
+}
+
+/// the delay between two listings of the queue of SLURM
+const SLURM_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+impl SlurmExecutor {
+    pub fn new(config: SlurmConfig) -> Self {
+        Self {
+            config,
+            queue: Mutex::new(JobQueue::default()),
+        }
+    }
+
+    /// the name of the job of task, as shown by squeue
+    fn job_name(task: &Task) -> String {
+        format!("zexp-{}-{}", std::process::id(), task.name)
+    }
+
+    /// the jobs among jobs that are in the queue of SLURM, queued or running
+    fn list_queue(jobs: &[&String]) -> io::Result<HashSet<String>> {
+        let ids: Vec<&str> = jobs.iter().map(|id| id.as_str()).collect();
+        let output = Command::new("squeue")
+            .args(["-h", "-o", "%i", "-j", &ids.join(",")])
+            .stdin(Stdio::null())
+            .output()?;
+        if !output.status.success() {
+            // e.g. one of the jobs left the queue long ago and is unknown now
+            return Err(io::Error::other(
+                String::from_utf8_lossy(&output.stderr).trim().to_string(),
+            ));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(|line| line.trim().to_string())
+            .collect())
+    }
+
+    /// how the job ended according to sacct, `None` if it has not ended
+    fn accounted_end(job_id: &str) -> io::Result<Option<JobEnd>> {
+        let output = Command::new("sacct")
+            .args(["-n", "-X", "-P", "-o", "State,ExitCode", "-j", job_id])
+            .stdin(Stdio::null())
+            .output()?;
+        if !output.status.success() {
+            return Err(io::Error::other(format!(
+                "sacct cannot tell how job {} ended: {}",
+                job_id,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        // nothing is printed until the job is accounted
+        Ok(String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .next()
+            .and_then(parse_sacct))
+    }
+}
+
+/// the end of a job from its line `State|ExitCode` printed by sacct, e.g. `FAILED|2:0`,
+/// `None` if it has not ended
+fn parse_sacct(line: &str) -> Option<JobEnd> {
+    let (state, exit_code) = line.trim().split_once('|')?;
+    // e.g. "CANCELLED by 1000"
+    let state = state.split_whitespace().next()?;
+    let (code, signal) = exit_code.split_once(':')?;
+    let code: i32 = code.parse().ok()?;
+    let signal: i32 = signal.parse().ok()?;
+    let outcome = match state {
+        "PENDING" | "RUNNING" | "REQUEUED" | "RESIZING" | "SUSPENDED" | "CONFIGURING"
+        | "COMPLETING" | "SIGNALING" | "STAGE_OUT" | "STOPPED" => return None,
+        "TIMEOUT" => TaskOutcome::TimedOut,
+        // OUT_OF_MEMORY jobs report 0:125, which is not a signal
+        _ if (1..=64).contains(&signal) => TaskOutcome::Signaled(signal),
+        "COMPLETED" if code == 0 => TaskOutcome::Ok,
+        _ if code != 0 => TaskOutcome::NonZero(code),
+        // e.g. NODE_FAIL or CANCELLED before it started, the task did not exit by itself
+        _ => TaskOutcome::NonZero(-1),
+    };
+    let note = match state {
+        "COMPLETED" | "FAILED" | "TIMEOUT" => None,
+        _ => Some(format!("the job ended in state {}", state)),
+    };
+    Some(JobEnd { outcome, note })
+}
+
+impl Executor for SlurmExecutor {
+    fn command(&self, task: &Task, mem_limit_mb: Option<u64>, outputs: &[PathBuf]) -> Command {
+        let mut command = Command::new("sbatch");
+        command
+            .args(["--parsable", "--open-mode=append"])
+            .arg(format!("--job-name={}", Self::job_name(task)));
+        if let Some(partition) = &self.config.partition {
+            command.arg(format!("--partition={}", partition));
+        }
+        if let Some(time) = &self.config.time {
+            command.arg(format!("--time={}", time));
+        }
+        match (&self.config.mem, mem_limit_mb) {
+            (Some(mem), _) => command.arg(format!("--mem={}", mem)),
+            (None, Some(mb)) => command.arg(format!("--mem={}M", mb)),
+            (None, None) => &mut command,
+        };
+        if let Some(cpus) = self.config.cpus {
+            command.arg(format!("--cpus-per-task={}", cpus));
+        }
+        if let Some(cwd) = &task.cwd {
+            command.arg(format!("--chdir={}", cwd.to_string_lossy()));
+        }
+        // the job is run from its own directory, the paths of the outputs must not be relative
+        let absolute = |path: &PathBuf| std::path::absolute(path).unwrap_or(path.clone());
+        let mut outputs = outputs.iter().map(absolute);
+        if let Some(stdout) = outputs.next() {
+            command.arg(format!("--output={}", stdout.to_string_lossy()));
+        }
+        if let Some(stderr) = outputs.next() {
+            command.arg(format!("--error={}", stderr.to_string_lossy()));
+        }
+        command.arg(format!("--wrap={}", task.exec_line()));
+        command
+    }
+
+    fn batch(&self) -> Option<&dyn BatchSystem> {
+        Some(self)
+    }
+}
+
+impl BatchSystem for SlurmExecutor {
+    /// the id printed by `sbatch --parsable`, `<id>` or `<id>;<cluster>`
+    fn job_id(&self, submission: &str) -> Option<String> {
+        let id = submission.lines().next()?.split(';').next()?.trim();
+        if id.is_empty() || !id.chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+        let mut queue = self.queue.lock().unwrap();
+        queue.jobs.insert(id.to_string(), Instant::now());
+        Some(id.to_string())
+    }
+
+    /// the queue is listed at most once per poll interval for all the jobs, then sacct is
+    /// asked about the jobs that are not in the queue, or about all of them if squeue fails
+    fn job_end(&self, job_id: &str) -> io::Result<Option<JobEnd>> {
+        {
+            let mut queue = self.queue.lock().unwrap();
+            let submitted = queue.jobs.get(job_id).copied();
+            // a listing done before the submission does not know the job
+            let stale = match queue.listed_at {
+                Some(at) => {
+                    at.elapsed() >= SLURM_POLL_INTERVAL || submitted.is_some_and(|s| s > at)
+                }
+                None => true,
+            };
+            if stale {
+                let jobs: Vec<&String> = queue.jobs.keys().collect();
+                let listed = match Self::list_queue(&jobs) {
+                    Ok(listed) => listed,
+                    Err(e) => {
+                        debug!("squeue failed, asking sacct: {}", e);
+                        HashSet::new()
+                    }
+                };
+                queue.listed = listed;
+                queue.listed_at = Some(Instant::now());
+            }
+            if queue.listed.contains(job_id) {
+                return Ok(None);
+            }
+        }
+        let end = Self::accounted_end(job_id)?;
+        if end.is_some() {
+            self.queue.lock().unwrap().jobs.remove(job_id);
+        }
+        Ok(end)
+    }
+
+    fn cancel(&self, job_id: &str) -> io::Result<()> {
+        let status = Command::new("scancel")
+            .arg(job_id)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()?;
+        if !status.success() {
+            debug!("scancel of job {} exited with {}", job_id, status);
+        }
+        Ok(())
+    }
+
+    fn poll_interval(&self) -> Duration {
+        SLURM_POLL_INTERVAL
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_local_executor() {
+        let task = Task::builder()
+            .cmd("sh")
+            .args(["-c", "nice; grep Cpus_allowed_list /proc/self/status"])
+            .build()
+            .unwrap();
+        let executor = LocalExecutor::new(Some(5), vec![0]);
+        let output = executor.command(&task, None, &[]).output().unwrap();
+        let output = String::from_utf8(output.stdout).unwrap();
+        let mut lines = output.lines();
+        let nice: i32 = lines.next().unwrap().parse().unwrap();
+        // at least 5, zexp itself may be niced
+        assert!(nice >= 5);
+        assert!(lines.next().unwrap().ends_with("\t0"));
+    }
+
+    #[test]
+    fn test_ssh_executor() {
+        let task = Task::builder()
+            .name("fft")
+            .cmd("otawa")
+            .args(["fft.elf", "a b"])
+            .env("LANG", "C")
+            .cwd("/data/tacle")
+            .build()
+            .unwrap();
+        let executor = SshExecutor::new(vec!["n1".to_string(), "n2".to_string()]);
+        let hosts: Vec<String> = (0..3)
+            .map(|_| {
+                let command = executor.command(&task, None, &[]);
+                assert_eq!(command.get_program(), "ssh");
+                command
+                    .get_args()
+                    .nth(2)
+                    .unwrap()
+                    .to_string_lossy()
+                    .to_string()
+            })
+            .collect();
+        assert_eq!(hosts, ["n1", "n2", "n1"]);
+
+        let command = executor.command(&task, Some(100), &[]);
+        assert_eq!(
+            command.get_args().last().unwrap(),
+            "ulimit -v 102400 && cd /data/tacle && LANG=C exec otawa fft.elf 'a b'"
+        );
+
+        // the line runs in a shell, with the environment of the task
+        let task = Task::builder()
+            .cmd("sh")
+            .args(["-c", "echo $ZEXP_TEST"])
+            .env("ZEXP_TEST", "a b")
+            .build()
+            .unwrap();
+        let output = Command::new("sh")
+            .args(["-c", &SshExecutor::remote_command_line(&task, None)])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        assert_eq!(output.stdout, b"a b\n");
+    }
+
+    #[test]
+    fn test_docker_executor() {
+        let task = Task::builder()
+            .name("fft/large")
+            .cmd("otawa")
+            .arg("fft.elf")
+            .env("LANG", "C")
+            .cwd("/data/tacle")
+            .build()
+            .unwrap();
+        let executor = DockerExecutor::new(DockerConfig {
+            image: "otawa:2.0".to_string(),
+            volumes: vec!["/opt:/opt:ro".to_string()],
+        });
+        let command = executor.command(&task, Some(512), &[]);
+        let args: Vec<String> = command
+            .get_args()
+            .map(|arg| arg.to_string_lossy().to_string())
+            .collect();
+        let name = format!("zexp-{}-fft_large", std::process::id());
+        assert_eq!(
+            args,
+            [
+                "run",
+                "--rm",
+                "--name",
+                &name,
+                "-v",
+                "/data/tacle:/data/tacle",
+                "-w",
+                "/data/tacle",
+                "-v",
+                "/opt:/opt:ro",
+                "-e",
+                "LANG=C",
+                "--memory=512m",
+                "otawa:2.0",
+                "otawa",
+                "fft.elf"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_slurm_executor() {
+        let task = Task::builder()
+            .name("fft")
+            .cmd("otawa")
+            .args(["fft.elf", "a b"])
+            .env("LANG", "C")
+            .build()
+            .unwrap();
+        let executor = SlurmExecutor::new(SlurmConfig {
+            partition: Some("short".to_string()),
+            time: Some("01:00:00".to_string()),
+            mem: None,
+            cpus: Some(2),
+        });
+        let outputs = [
+            PathBuf::from("/out/fft.stdout"),
+            PathBuf::from("/out/fft.stderr"),
+        ];
+        let command = executor.command(&task, Some(512), &outputs);
+        assert_eq!(command.get_program(), "sbatch");
+        let args: Vec<String> = command
+            .get_args()
+            .map(|arg| arg.to_string_lossy().to_string())
+            .collect();
+        let job_name = format!("--job-name=zexp-{}-fft", std::process::id());
+        assert_eq!(
+            args,
+            [
+                "--parsable",
+                "--open-mode=append",
+                &job_name,
+                "--partition=short",
+                "--time=01:00:00",
+                "--mem=512M",
+                "--cpus-per-task=2",
+                "--output=/out/fft.stdout",
+                "--error=/out/fft.stderr",
+                "--wrap=LANG=C exec otawa fft.elf 'a b'"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_slurm_job_end() {
+        let executor = SlurmExecutor::new(SlurmConfig::default());
+        assert_eq!(executor.job_id("1234\n"), Some("1234".to_string()));
+        assert_eq!(executor.job_id("1235;cluster\n"), Some("1235".to_string()));
+        assert_eq!(executor.job_id("sbatch: error\n"), None);
+        assert_eq!(executor.job_id(""), None);
+        assert_eq!(executor.queue.lock().unwrap().jobs.len(), 2);
+
+        let end = |outcome, note: Option<&str>| {
+            Some(JobEnd {
+                outcome,
+                note: note.map(str::to_string),
+            })
+        };
+        assert_eq!(parse_sacct("RUNNING|0:0"), None);
+        assert_eq!(parse_sacct("PENDING|0:0"), None);
+        assert_eq!(parse_sacct(""), None);
+        assert_eq!(parse_sacct("COMPLETED|0:0"), end(TaskOutcome::Ok, None));
+        assert_eq!(
+            parse_sacct("FAILED|2:0"),
+            end(TaskOutcome::NonZero(2), None)
+        );
+        assert_eq!(parse_sacct("TIMEOUT|0:0"), end(TaskOutcome::TimedOut, None));
+        assert_eq!(
+            parse_sacct("CANCELLED by 1000|0:15\n"),
+            end(
+                TaskOutcome::Signaled(15),
+                Some("the job ended in state CANCELLED")
+            )
+        );
+        assert_eq!(
+            parse_sacct("OUT_OF_MEMORY|0:125"),
+            end(
+                TaskOutcome::NonZero(-1),
+                Some("the job ended in state OUT_OF_MEMORY")
+            )
+        );
+        assert_eq!(
+            parse_sacct("NODE_FAIL|0:0"),
+            end(
+                TaskOutcome::NonZero(-1),
+                Some("the job ended in state NODE_FAIL")
+            )
+        );
+    }
+}
@@ -0,0 +1,41 @@
+//! ZExp runs experiments: a script describes the tasks to run, e.g. an analysis on each
+//! benchmark of TACLe or a command over a grid of parameters, and zexp runs them in parallel
+//! with a timeout, writing their outputs and results.
+//!
+//! The `rust-zexp` binary is the command line interface over this library, which can also
+//! be used on its own:
+//!
+//! ```no_run
+//! use rust_zexp::{load_script, run, RunOptions, Task};
+//!
+//! let mut tasks = load_script("exp.toml").unwrap().gen_cmd().unwrap();
+//! tasks.push(Task::builder().cmd("sleep").arg("1").build().unwrap());
+//! let opts = RunOptions {
+//!     default_timeout: Some(60),
+//!     merge_output: true,
+//!     ..Default::default()
+//! };
+//! let results = run(tasks, 4, opts);
+//! println!("{} tasks passed", results.iter().filter(|r| r.passed()).count());
+//! ```
+
+pub mod executor;
+pub mod progress;
+pub mod runner;
+pub mod script;
+mod sys;
+pub mod tacle;
+mod vars;
+
+pub use crate::executor::{Executor, LocalExecutor, SshExecutor};
+pub use crate::runner::{RunOptions, Runner, TaskResult};
+pub use crate::script::{
+    load_script, ConfigLoaderTrait, Hooks, LoadableFromConfig, LoaderRegistry, MainLoaderTrait,
+    Script, ScriptError, Task, TaskBuilder,
+};
+
+/// run tasks on cores workers, as the binary does without its options,
+/// use a `Runner` for its other features, e.g. the hooks or a CSV file of the results
+pub fn run(tasks: Vec<Task>, cores: usize, opts: RunOptions) -> Vec<TaskResult> {
+    Runner::new(tasks, opts).run(cores)
+}
@@ -1,106 +1,1443 @@
-use clap::Parser;
-use log::{error, info};
-use simplelog::{ColorChoice, Config, LevelFilter, TermLogger, TerminalMode};
+use clap::{CommandFactory, Parser, Subcommand};
+use indicatif::ProgressBar;
+use log::{error, info, warn};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use regex::Regex;
+use simplelog::{
+    ColorChoice, CombinedLogger, Config, ConfigBuilder, LevelFilter, SharedLogger, TermLogger,
+    TerminalMode, WriteLogger,
+};
+use std::collections::{BTreeMap, BTreeSet};
 use std::fs;
-use std::path::Path;
-use std::process::{exit, Command, Output};
-use std::sync::{Arc, Mutex};
-use std::time::Duration;
-use wait_timeout::ChildExt;
+use std::io::{self, IsTerminal};
+use std::path::{Path, PathBuf};
+use std::process::exit;
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
 
-mod script;
-mod tacle;
+use rust_zexp::executor::*;
+use rust_zexp::progress::*;
+use rust_zexp::runner::*;
+use rust_zexp::script::*;
 
-use crate::script::*;
+/// exit code when the run is interrupted, as a shell does for SIGINT
+const EXIT_INTERRUPTED: i32 = 130;
+
+/// exit code when the run is stopped by a failing task, with --fail-fast or --max-failures
+const EXIT_FAILED_FAST: i32 = 1;
 
 /// Run experince with ZExp!
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
-    /// The path to the script (in TOML format) to run
-    #[arg(short, long)]
-    script: String,
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    common: CommonArgs,
+
+    /// Run the tasks written by --emit-tasks (or `tasks emit`) in this file instead of the ones
+    /// of a script
+    #[arg(long, conflicts_with = "script", global = true)]
+    run_tasks: Option<PathBuf>,
+
+    /// Write the tasks to run to this file (in JSON), to run them later with --run-tasks
+    #[arg(long, global = true)]
+    emit_tasks: Option<PathBuf>,
+
+    /// Default timeout for tasks that do not specify one, e.g. `30m`, `2h` or `90` (in seconds),
+    /// no timeout if not given
+    #[arg(long, value_parser = parse_timeout, global = true)]
+    timeout: Option<u64>,
+
+    /// The directory where the outputs of the tasks are written, named after the tasks with `_` for
+    /// the characters unsafe in file names,
+    /// in a `<benchset>` subdirectory for the tasks of a benchset, with a `status.json` file of the
+    /// counts of pending, running, done and failed tasks rewritten while running
+    #[arg(long, default_value = "./zexp-out", global = true)]
+    out_dir: PathBuf,
+
+    /// Overwrite existing output files instead of adding a numeric suffix to the new ones
+    #[arg(long, global = true)]
+    overwrite: bool,
+
+    /// Write stdout and stderr of each task to a single `<name>.out` file
+    /// instead of separate `<name>.stdout` and `<name>.stderr` files
+    #[arg(long, global = true)]
+    merge_output: bool,
+
+    /// Compress the outputs of the tasks with gzip, to `<name>.out.gz` or `<name>.stdout.gz`
+    /// and `<name>.stderr.gz`, read them back with e.g. `zcat`
+    #[arg(long, global = true)]
+    compress: bool,
+
+    /// Truncate each output file of a task after this many bytes, ending it with
+    /// `...truncated...`, the rest of the output is dropped but the task goes on
+    #[arg(long, global = true)]
+    max_output_bytes: Option<u64>,
+
+    /// Kill the tasks whose output exceeds --max-output-bytes instead of letting them finish
+    #[arg(long, requires = "max_output_bytes", global = true)]
+    kill_on_max_output: bool,
+
+    /// Print the tasks that would be run, without running them
+    #[arg(long, global = true)]
+    dry_run: bool,
+
+    /// Print the name of each task, one per line, followed by a tab and its description if it
+    /// has one, without running them, the logs are written on stderr so that the list can be piped
+    #[arg(long, global = true)]
+    list: bool,
+
+    /// Like --list, followed by a tab and the command line of the task
+    #[arg(long, global = true)]
+    list_cmds: bool,
+
+    /// Check the script without running it: the tasks can be generated and their programs
+    /// can be run, exit with a non-zero code otherwise
+    #[arg(long, global = true)]
+    check: bool,
+
+    /// Print the configuration the script is run with, then exit: its included files merged,
+    /// its `${env:NAME}` interpolated and the loaders it defaults to made explicit
+    #[arg(long, conflicts_with = "run_tasks", global = true)]
+    print_config: bool,
+
+    /// Only run the tasks whose name matches this regex
+    #[arg(long, global = true)]
+    filter: Option<Regex>,
+
+    /// Do not run the tasks whose name matches this regex
+    #[arg(long, global = true)]
+    exclude: Option<Regex>,
+
+    /// Where to write the JSON results of the run, `<out-dir>/results.json` by default
+    #[arg(long, global = true)]
+    results_file: Option<PathBuf>,
+
+    /// Also write the results to this CSV file, one row per task as soon as it terminates
+    #[arg(long, global = true)]
+    csv: Option<PathBuf>,
+
+    /// Write a JSON object per line to this file (`-` for stderr) as each task starts
+    /// and terminates, for a monitor following the run,
+    /// e.g. `{"event":"finish","name":"fft","code":0,"duration":1.5,...}`
+    #[arg(long, global = true)]
+    events_file: Option<PathBuf>,
+
+    /// Number of times a failing task is rerun, for tasks that do not specify it
+    #[arg(long, default_value_t = 0, global = true)]
+    retries: u32,
+
+    /// Wait at least this many milliseconds between the starts of two tasks, so that the load
+    /// ramps up gradually instead of starting -j tasks at once
+    #[arg(long, global = true)]
+    stagger_ms: Option<u64>,
+
+    /// Stop starting tasks once the run has lasted this long, e.g. `6h`, `90m` or `3600`
+    /// (in seconds), the running tasks are let finish, or killed with --fail-fast,
+    /// and the tasks not started are in the results as not run
+    #[arg(long, value_parser = parse_duration, global = true)]
+    max_runtime: Option<Duration>,
+
+    /// Rename the tasks of the same name `<benchset>_<name>`, instead of aborting the run
+    #[arg(long, global = true)]
+    auto_rename: bool,
+
+    /// Run each task this many times, e.g. to average its duration, the runs of a task
+    /// are named `<name>#<k>` and labelled `repeat`, unlike --retries they are all run
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..), global = true)]
+    repeat: u32,
+
+    /// Run the tasks in a random order, e.g. so that the small ones are not all run first,
+    /// shuffled with the given seed to get the same order again, or a random one (logged)
+    #[arg(long, num_args = 0..=1, value_name = "SEED", global = true)]
+    shuffle: Option<Option<u64>>,
+
+    /// Run each task once before its measured run, e.g. to warm up the caches, the result
+    /// and the outputs of this run are dropped, with --repeat only the first run is preceded by it
+    #[arg(long, global = true)]
+    warmup: bool,
+
+    /// Also rerun the tasks that timed out
+    #[arg(long, global = true)]
+    retry_on_timeout: bool,
+
+    /// Skip the tasks already done: the ones that passed in the previous results file,
+    /// or whose `<name>.out` (or `<name>.stdout`) output file exists and is not empty
+    #[arg(long, global = true)]
+    resume: bool,
+
+    /// Only run the tasks that did not pass in this results file of a previous run,
+    /// e.g. the ones that failed or timed out, all the runs of a task given by --repeat
+    #[arg(long, value_name = "RESULTS", global = true)]
+    rerun_failed: Option<PathBuf>,
+
+    /// Start the tasks by decreasing duration in this results file of a previous run, so that no
+    /// long task is left for the end while the other cores are idle, the tasks not in the file
+    /// keep the `weight` of their bench if any, or are given the mean one
+    #[arg(long, value_name = "RESULTS", global = true)]
+    weights: Option<PathBuf>,
+
+    /// Also log how many tasks passed for each value of this label, e.g. `benchset`
+    #[arg(long, global = true)]
+    group_by: Option<String>,
+
+    /// Log the last N lines of the outputs of each task that fails or times out, as soon as it
+    /// terminates
+    #[arg(long, value_name = "N", global = true)]
+    tail: Option<usize>,
 
-    /// Number of cores you want to use
-    #[arg(short, default_value_t = 1)]
+    /// Stop the run as soon as a task fails or times out, killing the running tasks
+    #[arg(long, global = true)]
+    fail_fast: bool,
+
+    /// Stop the run once this many tasks failed or timed out, killing the running tasks,
+    /// a few failures are tolerated but not a clearly broken run
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..), conflicts_with = "fail_fast", global = true)]
+    max_failures: Option<u32>,
+
+    /// Default memory limit (in MB) of the tasks that do not specify one, on Linux only
+    #[arg(long, global = true)]
+    mem_limit: Option<u64>,
+
+    /// Do not check that the program of each task exists and is executable before running them
+    #[arg(long, global = true)]
+    no_preflight: bool,
+
+    /// Do not write the header line (`# zexp: <command line> (at <date> on <host>)`)
+    /// at the top of the output files
+    #[arg(long, global = true)]
+    no_header: bool,
+
+    /// Run the tasks on these hosts over SSH, taking them in turn, instead of locally,
+    /// e.g. `node1,node2`, -j is then the number of tasks run at once on all the hosts,
+    /// the hooks are still run locally
+    #[arg(long, value_delimiter = ',', global = true)]
+    hosts: Vec<String>,
+
+    /// Niceness of the tasks run locally, from -20 (highest priority) to 19 (lowest),
+    /// e.g. 19 to keep working while they run, on Unix only
+    #[arg(long, allow_hyphen_values = true, value_parser = clap::value_parser!(i32).range(-20..=19), global = true)]
+    nice: Option<i32>,
+
+    /// Pin the workers to these cores, e.g. `0-3,8`, the tasks of the n-th worker are run
+    /// on the n-th core, on Linux only
+    #[arg(long, value_parser = parse_cores, global = true)]
+    cpu_affinity: Option<Vec<usize>>,
+
+    /// Do not show the progress bar of the run
+    #[arg(long, global = true)]
+    no_progress: bool,
+
+    /// Arguments appended to the command of every task, after `--`,
+    /// e.g. `zexp -s x.toml -- --extra-prop foo`
+    #[arg(last = true)]
+    extra_args: Vec<String>,
+}
+
+/// The options of every command: where the script is, how it is loaded, and how much is logged
+#[derive(clap::Args, Debug)]
+struct CommonArgs {
+    /// The path to the script (in TOML format) to run, `-` to read it from stdin,
+    /// as when neither it nor --run-tasks is given
+    #[arg(short, long, global = true)]
+    script: Option<String>,
+
+    /// Number of cores you want to use, `auto` or 0 to use all the available ones,
+    /// with a SLURM table the number of jobs submitted at once
+    #[arg(short, default_value = "1", value_parser = parse_jobs, global = true)]
     j: usize,
+
+    /// Level of the logs shown on the terminal: off, error, warn, info, debug or trace,
+    /// the start and end of each task are logged at debug level
+    #[arg(long, default_value = "info", value_parser = parse_log_level, global = true)]
+    log_level: LevelFilter,
+
+    /// Also write the full log to this file, appended to it if it exists
+    #[arg(long, global = true)]
+    log_file: Option<PathBuf>,
+
+    /// Run the benchset a name of tacle_run_benchset is a prefix of, or is the closest to,
+    /// when there is no benchset of this name
+    #[arg(long, global = true)]
+    fuzzy: bool,
+
+    /// Read the benchs of the TACLE table from this CSV file with a header line, e.g.
+    /// `name,exec,entry_point`, instead of its tacle_desc_path, see `tacle_desc_csv`
+    #[arg(long, conflicts_with = "run_tasks", global = true)]
+    benches_csv: Option<PathBuf>,
+}
+
+/// The commands of zexp, each one is a shorthand for flags of the run, which it runs when no
+/// command is given: e.g. `zexp list -s x.toml` is `zexp -s x.toml --list`
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Run the tasks of the script
+    Run {
+        /// Arguments appended to the command of every task, after `--`
+        #[arg(last = true)]
+        extra_args: Vec<String>,
+    },
+    /// Print the name of each task, one per line, as --list
+    List {
+        /// Also print the command line of each task, as --list-cmds
+        #[arg(long)]
+        cmds: bool,
+    },
+    /// Check the script without running it, as --check
+    #[command(alias = "validate")]
+    Check,
+    /// Print the configuration the script is run with, as --print-config
+    Config,
+    /// Write the tasks of a script to a file, or run the tasks of such a file
+    #[command(subcommand)]
+    Tasks(TasksCommand),
+}
+
+#[derive(Subcommand, Debug)]
+enum TasksCommand {
+    /// Write the tasks of the script to this file (in JSON) without running them
+    Emit { file: PathBuf },
+    /// Run the tasks written by `tasks emit` in this file, as --run-tasks
+    Run { file: PathBuf },
+}
+
+impl Args {
+    /// turn the command into the flags it stands for, fail if it is `tasks run` with a script
+    fn apply_command(&mut self) -> Result<(), clap::Error> {
+        match &self.command {
+            None => {}
+            Some(Command::Run { extra_args }) => self.extra_args.extend(extra_args.clone()),
+            Some(Command::List { cmds }) => {
+                self.list = true;
+                self.list_cmds = *cmds;
+            }
+            Some(Command::Check) => self.check = true,
+            Some(Command::Config) => self.print_config = true,
+            Some(Command::Tasks(TasksCommand::Emit { file })) => {
+                self.emit_tasks = Some(file.clone())
+            }
+            Some(Command::Tasks(TasksCommand::Run { file })) => {
+                if self.common.script.is_some() {
+                    return Err(Args::command().error(
+                        clap::error::ErrorKind::ArgumentConflict,
+                        "`tasks run` runs the tasks of its file, not the ones of --script",
+                    ));
+                }
+                self.run_tasks = Some(file.clone())
+            }
+        }
+        Ok(())
+    }
+
+    /// only write the tasks with `tasks emit`, instead of also running them as --emit-tasks does
+    fn emit_only(&self) -> bool {
+        matches!(
+            self.command,
+            Some(Command::Tasks(TasksCommand::Emit { .. }))
+        )
+    }
+}
+
+/// the sum of the durations of the tasks, and its ratio to the wall-clock duration of the run
+fn speedup(results: &[TaskResult], elapsed: Duration) -> (Duration, f64) {
+    let task_time: Duration = results.iter().map(|r| r.duration).sum();
+    let speedup = if elapsed.is_zero() {
+        1.0
+    } else {
+        task_time.as_secs_f64() / elapsed.as_secs_f64()
+    };
+    (task_time, speedup)
+}
+
+/// for each value of the label key, the number of tasks that passed and the number of tasks,
+/// the tasks without this label are counted under "-"
+fn group_counts<'a>(results: &'a [TaskResult], key: &str) -> BTreeMap<&'a str, (usize, usize)> {
+    let mut res = BTreeMap::new();
+    for result in results {
+        let value = result.labels.get(key).map_or("-", String::as_str);
+        let (passed, total) = res.entry(value).or_insert((0, 0));
+        if result.passed() {
+            *passed += 1;
+        }
+        *total += 1;
+    }
+    res
+}
+
+/// the line logged before the run: how many tasks there are, per benchset when they have one
+fn scope_line(tasks: &[Task]) -> String {
+    let mut per_benchset: BTreeMap<&str, usize> = BTreeMap::new();
+    for task in tasks {
+        if let Some(benchset) = &task.benchset {
+            *per_benchset.entry(benchset).or_default() += 1;
+        }
+    }
+    if per_benchset.is_empty() {
+        return format!("Generated {} tasks", tasks.len());
+    }
+    let counts: Vec<String> = per_benchset
+        .iter()
+        .map(|(benchset, count)| format!("{}: {}", benchset, count))
+        .collect();
+    format!(
+        "Generated {} tasks across {} benchsets ({})",
+        tasks.len(),
+        per_benchset.len(),
+        counts.join(", ")
+    )
+}
+
+/// log how many tasks passed, failed (and were killed by a signal) and timed out, and the name
+/// and outcome of the unsuccessful ones,
+/// and how long the run took, elapsed being its wall-clock duration,
+/// with group_by the number of tasks that passed for each value of this label
+fn log_summary(results: &[TaskResult], elapsed: Duration, group_by: Option<&str>) {
+    let passed = results.iter().filter(|r| r.passed()).count();
+    let timed_out = results.iter().filter(|r| r.timed_out).count();
+    let skipped = results.iter().filter(|r| r.skipped).count();
+    let interrupted = results.iter().filter(|r| r.interrupted).count();
+    let not_run = results.iter().filter(|r| r.not_started()).count();
+    let failed = results.len() - passed - timed_out - skipped - interrupted - not_run;
+    // the failed tasks whose program crashed, rather than exited with an error
+    let signaled = results
+        .iter()
+        .filter(|r| !r.interrupted && matches!(r.outcome, Some(TaskOutcome::Signaled(_))))
+        .count();
+
+    info!("========== Summary ==========");
+    info!("{:<12} {:>6}", "passed", passed);
+    info!("{:<12} {:>6}", "failed", failed);
+    info!("{:<12} {:>6}", "  by signal", signaled);
+    info!("{:<12} {:>6}", "timed out", timed_out);
+    info!("{:<12} {:>6}", "skipped", skipped);
+    info!("{:<12} {:>6}", "interrupted", interrupted);
+    info!("{:<12} {:>6}", "not run", not_run);
+    info!("{:<12} {:>6}", "total", results.len());
+    let (task_time, speedup) = speedup(results, elapsed);
+    info!("{:<12} {:>6.1?}", "wall-clock", elapsed);
+    info!("{:<12} {:>6.1?}", "task time", task_time);
+    info!("{:<12} {:>6.2}x", "speedup", speedup);
+    if let Some(key) = group_by {
+        info!("========== By {} ==========", key);
+        for (value, (passed, total)) in group_counts(results, key) {
+            info!("{:<12} {:>6}/{} passed", value, passed, total);
+        }
+    }
+    for result in results
+        .iter()
+        .filter(|r| !r.passed() && !r.skipped && !r.interrupted && !r.not_started())
+    {
+        match &result.outcome {
+            _ if result.timed_out => {
+                error!("{} timed out after {:?}", result.name, result.duration)
+            }
+            // the note is the error then
+            Some(TaskOutcome::SpawnFailed(_)) => {}
+            Some(outcome) => error!("{} {}", result.name, outcome),
+            None => error!("{} failed with code {:?}", result.name, result.exit_code),
+        }
+        if let Some(note) = &result.note {
+            error!("{} {}", result.name, note);
+        }
+    }
+}
+
+/// append extra to the arguments of every task, an error if a task is then not completed,
+/// e.g. because extra has a `$var`
+fn append_args(tasks: &mut [Task], extra: &[String]) -> Result<(), String> {
+    for task in tasks.iter_mut() {
+        task.args.extend_from_slice(extra);
+        task.check_completed()?;
+    }
+    Ok(())
+}
+
+/// the name of the task of result, without the `#<k>` of the runs of a repeated task
+fn base_name(result: &TaskResult) -> &str {
+    match result.labels.get("repeat") {
+        Some(k) => result
+            .name
+            .strip_suffix(&format!("#{}", k))
+            .unwrap_or(&result.name),
+        None => &result.name,
+    }
+}
+
+/// weight each task by its mean duration in seconds in previous, the results of a previous run,
+/// the runs of a repeated task are given by the name of the task, without `#<k>`,
+/// the tasks that were not run keep their weight, return the number of tasks weighted so
+fn seed_weights(tasks: &mut [Task], previous: &[TaskResult]) -> usize {
+    let mut durations: BTreeMap<&str, (f64, u32)> = BTreeMap::new();
+    // the duration of an interrupted task is not the one of a whole run, and the tasks
+    // skipped or not run, e.g. after the maximum runtime, have none
+    for result in previous.iter().filter(|r| r.attempts > 0 && !r.interrupted) {
+        let (total, runs) = durations.entry(base_name(result)).or_default();
+        *total += result.duration.as_secs_f64();
+        *runs += 1;
+    }
+    let mut weighted = 0;
+    for task in tasks.iter_mut() {
+        if let Some((total, runs)) = durations.get(task.name.as_str()) {
+            task.weight = Some(total / *runs as f64);
+            weighted += 1;
+        }
+    }
+    weighted
+}
+
+/// keep the tasks that did not pass in previous, the results of a previous run,
+/// an error naming them if some of them are not in tasks,
+/// the runs of a repeated task are given by the name of the task, without `#<k>`
+fn select_failed(tasks: Vec<Task>, previous: &[TaskResult]) -> Result<Vec<Task>, String> {
+    let failed: BTreeSet<&str> = previous
+        .iter()
+        .filter(|result| !result.passed() && !result.skipped)
+        .map(base_name)
+        .collect();
+    let missing: Vec<&str> = failed
+        .iter()
+        .filter(|name| !tasks.iter().any(|task| task.name == **name))
+        .copied()
+        .collect();
+    if !missing.is_empty() {
+        return Err(format!(
+            "the failed tasks {} are not generated by the script anymore",
+            missing.join(", ")
+        ));
+    }
+    Ok(tasks
+        .into_iter()
+        .filter(|task| failed.contains(task.name.as_str()))
+        .collect())
+}
+
+/// keep the tasks whose name matches `filter` (if any) and does not match `exclude` (if any)
+fn filter_tasks(tasks: Vec<Task>, filter: Option<&Regex>, exclude: Option<&Regex>) -> Vec<Task> {
+    tasks
+        .into_iter()
+        .filter(|task| filter.is_none_or(|re| re.is_match(&task.name)))
+        .filter(|task| exclude.is_none_or(|re| !re.is_match(&task.name)))
+        .collect()
+}
+
+/// the tasks, each one repeated n times as `<name>#<k>` with k from 1 to n,
+/// unchanged if n is 1, only the first run of a task is warmed up, see `Task::warmup`
+fn repeat_tasks(tasks: Vec<Task>, n: u32) -> Vec<Task> {
+    if n == 1 {
+        return tasks;
+    }
+    tasks
+        .into_iter()
+        .flat_map(|task| {
+            (1..=n).map(move |k| {
+                let mut task = task.clone();
+                task.name = format!("{}#{}", task.name, k);
+                task.labels.insert("repeat".to_string(), k.to_string());
+                task.warmup &= k == 1;
+                task
+            })
+        })
+        .collect()
 }
 
-fn run_tasks_concurrently(tasks: &Vec<Task>, num_cores: usize) {
-    // Create a thread pool with the specified number of cores
-    let pool = rayon::ThreadPoolBuilder::new()
-        .num_threads(num_cores)
-        .build()
-        .unwrap();
-
-    let tasks = Arc::new(Mutex::new(tasks.clone()));
-    for _ in 0..num_cores {
-        let tasks = Arc::clone(&tasks);
-        pool.scope(|s| {
-            while let Some(task) = {
-                let mut tasks_guard = tasks.lock().unwrap();
-                tasks_guard.pop()
-            } {
-                s.spawn(move |_| {
-                    info!("Running task: {}", &task.name);
-                    let mut fout = task.name.clone();
-                    fout.push_str(".out");
-                    let fout = fs::File::create(fout).unwrap();
-                    let mut child = Command::new(&task.cmd)
-                        .args(&task.args)
-                        .stderr(fout.try_clone().unwrap())
-                        .stdout(fout)
-                        .spawn()
-                        .expect("Failed to execute command");
-                    info!("Task {} terminated", task.name);
-
-                    // timeout for 2 hours
-                    let two_hours = Duration::from_secs(3600);
-                    match child.wait_timeout(two_hours).unwrap() {
-                        Some(status) => {
-                            return;
-                        }
-                        None => {
-                            info!("Task {} timed out, killed", task.name);
-                            // timeout, kill it
-                            child.kill().unwrap();
-                            child.wait().unwrap().code()
-                        }
-                    };
-                })
+/// shuffle the tasks, always in the same order for a given seed
+fn shuffle_tasks(tasks: &mut [Task], seed: u64) {
+    tasks.shuffle(&mut StdRng::seed_from_u64(seed));
+}
+
+/// print the name and the command line of each task on stdout
+fn print_tasks(tasks: &[Task]) {
+    for task in tasks {
+        match &task.description {
+            Some(description) => {
+                println!("{}: {}  # {}", task.name, task.command_line(), description)
             }
+            None => println!("{}: {}", task.name, task.command_line()),
+        }
+    }
+}
+
+/// the problems found by `--check` in the generated tasks: there is none,
+/// or their programs cannot be run (see `preflight`)
+fn check_tasks(tasks: &[Task]) -> Vec<String> {
+    let mut problems = Vec::new();
+    if tasks.is_empty() {
+        problems.push("no task is generated, e.g. the selected benchsets are empty".to_string());
+    }
+    if let Err(errors) = preflight(tasks) {
+        problems.extend(errors);
+    }
+    problems
+}
+
+/// the lines printed by `--list`, the name of each task, and its command line after a tab
+/// if with_cmds is set, then its description after a tab if it has one
+fn list_lines(tasks: &[Task], with_cmds: bool) -> Vec<String> {
+    tasks
+        .iter()
+        .map(|task| {
+            let mut line = task.name.clone();
+            if with_cmds {
+                line = format!("{}\t{}", line, task.command_line());
+            }
+            if let Some(description) = &task.description {
+                line = format!("{}\t{}", line, description);
+            }
+            line
         })
+        .collect()
+}
+
+/// parse the number of cores given to `-j`, `auto` being 0
+fn parse_jobs(jobs: &str) -> Result<usize, String> {
+    if jobs == "auto" {
+        return Ok(0);
     }
+    jobs.parse()
+        .map_err(|_| format!("expected a number of cores or auto, found {}", jobs))
 }
 
-fn main() {
-    TermLogger::init(
-        LevelFilter::Trace,
+/// parse a list of cores such as `0-3,8`
+fn parse_cores(cores: &str) -> Result<Vec<usize>, String> {
+    let core = |core: &str| {
+        core.trim()
+            .parse::<usize>()
+            .map_err(|_| format!("expected a core number, found {}", core))
+    };
+    let mut parsed = Vec::new();
+    for range in cores.split(',') {
+        match range.split_once('-') {
+            Some((first, last)) => parsed.extend(core(first)?..=core(last)?),
+            None => parsed.push(core(range)?),
+        }
+    }
+    Ok(parsed)
+}
+
+/// parse a timeout in seconds, given as a duration such as `30m`, see `parse_duration`
+fn parse_timeout(timeout: &str) -> Result<u64, String> {
+    parse_duration(timeout).map(|timeout| timeout.as_secs())
+}
+
+/// the number of cores that can run in parallel, 1 if it cannot be detected
+fn available_cores() -> usize {
+    std::thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+/// the number of cores to use for `-j jobs`, all the available ones for 0
+fn resolve_jobs(jobs: usize, available: usize) -> usize {
+    match jobs {
+        0 => available,
+        jobs => jobs,
+    }
+}
+
+/// a warning when more cores than available are requested, the tasks then slow each other down
+fn oversubscription_warning(num_cores: usize, available: usize) -> Option<String> {
+    (num_cores > available).then(|| {
+        format!(
+            "{} cores requested but only {} available, consider -j {}",
+            num_cores, available, available
+        )
+    })
+}
+
+/// parse the level given to `--log-level`, whatever its case
+fn parse_log_level(level: &str) -> Result<LevelFilter, String> {
+    match level.to_ascii_lowercase().as_str() {
+        "off" => Ok(LevelFilter::Off),
+        "error" => Ok(LevelFilter::Error),
+        "warn" => Ok(LevelFilter::Warn),
+        "info" => Ok(LevelFilter::Info),
+        "debug" => Ok(LevelFilter::Debug),
+        "trace" => Ok(LevelFilter::Trace),
+        _ => Err(format!(
+            "unknown log level {}, expected one of off, error, warn, info, debug, trace",
+            level
+        )),
+    }
+}
+
+/// log on the terminal at level, the progress bar being hidden while logging,
+/// and at trace level to log_file if given
+fn init_logger(
+    level: LevelFilter,
+    mode: TerminalMode,
+    log_file: Option<&Path>,
+    bar: ProgressBar,
+) -> io::Result<()> {
+    let mut loggers: Vec<Box<dyn SharedLogger>> = vec![TermLogger::new(
+        level,
         Config::default(),
-        TerminalMode::Mixed,
+        mode,
         ColorChoice::Auto,
-    )
+    )];
+    if let Some(path) = log_file {
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?;
+        // the terminal config only shows the time, the file needs the date too
+        let config = ConfigBuilder::new().set_time_format_rfc3339().build();
+        loggers.push(WriteLogger::new(LevelFilter::Trace, config, file));
+    }
+    log::set_boxed_logger(Box::new(ProgressLogger::new(
+        CombinedLogger::new(loggers),
+        bar,
+    )))
     .unwrap();
+    log::set_max_level(LevelFilter::Trace);
+    Ok(())
+}
+
+/// load the script, from stdin if its path is `-`, with its loaders, exit on error
+fn load_script_arg(script_path: &str, fuzzy_benchset: bool, benches_csv: Option<&Path>) -> Script {
+    let script = if script_path == "-" {
+        info!("script read from stdin");
+        let stdin = io::stdin();
+        if stdin.is_terminal() {
+            error!("No script given, and stdin is a terminal, see --help");
+            exit(-1);
+        }
+        Script::from_reader(stdin.lock(), "<stdin>")
+    } else {
+        info!("script path: {:?}", script_path);
+        match Path::new(script_path).try_exists() {
+            Ok(true) => {}
+            Ok(false) => {
+                error!("script file does not exist, Aborting...");
+                exit(-1);
+            }
+            Err(_) => {
+                error!("Error when checking if script file exists");
+                exit(-1);
+            }
+        }
+        Script::from_file(script_path)
+    };
+    let loaded = script.and_then(|mut script| {
+        if fuzzy_benchset {
+            script.set_fuzzy_benchset();
+        }
+        if let Some(csv) = benches_csv {
+            script.set_tacle_desc_csv(&csv.to_string_lossy())?;
+        }
+        script.register_loaders(&LoaderRegistry::default())?;
+        Ok(script)
+    });
+    match loaded {
+        Ok(script) => script,
+        Err(e) => {
+            error!("Cannot load the script: {}", e);
+            exit(-1);
+        }
+    }
+}
+
+/// load the script, from stdin if its path is `-`, and generate its tasks and hooks,
+/// with their executor if they are not run locally, exit on error
+fn script_tasks(
+    script_path: &str,
+    fuzzy_benchset: bool,
+    benches_csv: Option<&Path>,
+) -> (Vec<Task>, Hooks, Option<Box<dyn Executor>>) {
+    let mut script = load_script_arg(script_path, fuzzy_benchset, benches_csv);
+    let cmd = match script.gen_cmd() {
+        Ok(cmd) => cmd,
+        Err(e) => {
+            error!("Cannot generate the tasks: {}", e);
+            exit(-1);
+        }
+    };
+    let hooks = match script.gen_hooks() {
+        Ok(hooks) => hooks,
+        Err(e) => {
+            error!("Cannot generate the hooks: {}", e);
+            exit(-1);
+        }
+    };
+    match script.gen_executor() {
+        Ok(executor) => (cmd, hooks, executor),
+        Err(e) => {
+            error!("Cannot load the script: {}", e);
+            exit(-1);
+        }
+    }
+}
 
-    let args = Args::parse();
-    let script_path = args.script;
-    let num_cores = args.j;
-    info!("script path: {:?}", script_path);
+fn main() {
+    let mut args = Args::parse();
+    if let Err(e) = args.apply_command() {
+        e.exit();
+    }
+    // hidden until the run starts, see `Progress::start`
+    let bar = ProgressBar::hidden();
+    let list = args.list || args.list_cmds;
+    // keep stdout for the list or the config only
+    let mode = if list || args.print_config {
+        TerminalMode::Stderr
+    } else {
+        TerminalMode::Mixed
+    };
+    if let Err(e) = init_logger(
+        args.common.log_level,
+        mode,
+        args.common.log_file.as_deref(),
+        bar.clone(),
+    ) {
+        eprintln!(
+            "Cannot open the log file {:?}: {}",
+            args.common.log_file.unwrap(),
+            e
+        );
+        exit(-1);
+    }
+
+    let available = available_cores();
+    let num_cores = resolve_jobs(args.common.j, available);
     info!("cores number: {:?}", num_cores);
+    if let Some(warning) = oversubscription_warning(num_cores, available) {
+        warn!("{}", warning);
+    }
+
+    if args.print_config {
+        let script_path = args.common.script.as_deref().unwrap_or("-");
+        let config = load_script_arg(
+            script_path,
+            args.common.fuzzy,
+            args.common.benches_csv.as_deref(),
+        )
+        .effective_config()
+        .map_err(|e| e.to_string())
+        .and_then(|config| toml::to_string(&config).map_err(|e| e.to_string()));
+        match config {
+            Ok(config) => print!("{}", config),
+            Err(e) => {
+                error!("Cannot print the configuration: {}", e);
+                exit(-1);
+            }
+        }
+        return;
+    }
+
+    let (cmd, hooks, executor) = match (&args.run_tasks, &args.common.script) {
+        (Some(tasks_path), _) => {
+            info!("tasks path: {:?}", tasks_path);
+            match read_tasks(tasks_path) {
+                Ok(cmd) => (cmd, Hooks::default(), None),
+                Err(e) => {
+                    error!("Cannot read the tasks {:?}: {}", tasks_path, e);
+                    exit(-1);
+                }
+            }
+        }
+        (None, script_path) => script_tasks(
+            script_path.as_deref().unwrap_or("-"),
+            args.common.fuzzy,
+            args.common.benches_csv.as_deref(),
+        ),
+    };
+
+    let mut cmd = cmd;
+    if let Err(e) = append_args(&mut cmd, &args.extra_args) {
+        error!("Invalid extra arguments: {}", e);
+        exit(-1);
+    }
+    if args.auto_rename {
+        rename_duplicates(&mut cmd);
+    }
+    let duplicates = duplicate_names(&cmd);
+    if !duplicates.is_empty() {
+        error!(
+            "Several tasks are named {}, their outputs would collide{}",
+            duplicates.join(", "),
+            if args.auto_rename {
+                ""
+            } else {
+                ", --auto-rename prefixes them with their benchset"
+            }
+        );
+        exit(-1);
+    }
+
+    if let Some(path) = &args.rerun_failed {
+        let selected = read_results(path)
+            .map_err(|e| format!("cannot read the results {:?}: {}", path, e))
+            .and_then(|previous| select_failed(cmd, &previous));
+        cmd = match selected {
+            Ok(cmd) => cmd,
+            Err(e) => {
+                error!("Cannot rerun the failed tasks: {}", e);
+                exit(-1);
+            }
+        };
+        info!("{} failed tasks rerun", cmd.len());
+    }
+
+    let total = cmd.len();
+    let mut cmd = filter_tasks(cmd, args.filter.as_ref(), args.exclude.as_ref());
+    info!(
+        "{} tasks retained, {} filtered out",
+        cmd.len(),
+        total - cmd.len()
+    );
+    if let Some(path) = &args.weights {
+        match read_results(path) {
+            Ok(previous) => {
+                let weighted = seed_weights(&mut cmd, &previous);
+                info!("{} tasks weighted by their previous duration", weighted);
+            }
+            Err(e) => {
+                error!("Cannot read the results {:?}: {}", path, e);
+                exit(-1);
+            }
+        }
+    }
+    if args.warmup {
+        for task in cmd.iter_mut() {
+            task.warmup = true;
+        }
+    }
+    let mut cmd = repeat_tasks(cmd, args.repeat);
+    if let Some(seed) = args.shuffle {
+        let seed = seed.unwrap_or_else(rand::random);
+        info!("Tasks shuffled with the seed {}", seed);
+        shuffle_tasks(&mut cmd, seed);
+    }
+    info!("{}", scope_line(&cmd));
 
-    match Path::new(&script_path).try_exists() {
-        Ok(true) => {}
-        Ok(false) => {
-            error!("script file does not exist, Aborting...");
+    if let Some(path) = &args.emit_tasks {
+        match write_tasks(path, &cmd) {
+            Ok(()) => info!("Tasks written to {:?}", path),
+            Err(e) => {
+                error!("Cannot write the tasks to {:?}: {}", path, e);
+                exit(-1);
+            }
+        }
+        if args.emit_only() {
+            return;
+        }
+    }
+
+    if list {
+        for line in list_lines(&cmd, args.list_cmds) {
+            println!("{}", line);
+        }
+        return;
+    }
+
+    if args.check {
+        let problems = check_tasks(&cmd);
+        if !problems.is_empty() {
+            for problem in &problems {
+                error!("{}", problem);
+            }
+            error!("The script is not valid");
             exit(-1);
         }
-        Err(_) => {
-            error!("Error when checking if script file exists");
+        info!("The script is valid, {} tasks", cmd.len());
+        return;
+    }
+
+    if args.dry_run {
+        print_tasks(&cmd);
+        if cmd.iter().any(|task| !task.is_completed()) {
+            error!("Some tasks are not completed, Aborting...");
             exit(-1);
         }
+        return;
     }
 
-    let mut script = otawa_tacle_script(&script_path);
-    let cmd = script.gen_cmd().unwrap();
-    run_tasks_concurrently(&cmd, num_cores);
+    if let Err(e) = fs::create_dir_all(&args.out_dir) {
+        error!(
+            "Cannot create the output directory {:?}: {}",
+            args.out_dir, e
+        );
+        exit(-1);
+    }
+    let results_file = args
+        .results_file
+        .unwrap_or_else(|| args.out_dir.join("results.json"));
+    let status_file = args.out_dir.join("status.json");
+    let opts = RunOptions {
+        default_timeout: args.timeout,
+        out_dir: args.out_dir,
+        overwrite: args.overwrite,
+        merge_output: args.merge_output,
+        retries: args.retries,
+        retry_on_timeout: args.retry_on_timeout,
+        resume: args.resume,
+        mem_limit_mb: args.mem_limit,
+        fail_fast: args.fail_fast,
+        max_failures: args.max_failures,
+        header: !args.no_header,
+        stagger: args.stagger_ms.map(Duration::from_millis),
+        compress: args.compress,
+        max_runtime: args.max_runtime,
+        max_output_bytes: args.max_output_bytes,
+        kill_on_max_output: args.kill_on_max_output,
+        tail: args.tail,
+    };
+    let mut runner = Runner::new(cmd, opts);
+    runner.set_hooks(hooks);
+    // the events written to stderr would be mixed with the bar
+    let events_on_stderr = args.events_file.as_deref() == Some(Path::new("-"));
+    if !args.no_progress && !events_on_stderr {
+        runner.set_progress(Progress::new(bar, num_cores));
+    }
+    // the programs are the ones of the hosts or of the image, they cannot be checked here
+    let remote = !args.hosts.is_empty() || executor.is_some();
+    if remote && (args.nice.is_some() || args.cpu_affinity.is_some()) {
+        warn!("--nice and --cpu-affinity are ignored, the tasks are not run locally");
+    }
+    match executor {
+        Some(_) if !args.hosts.is_empty() => {
+            error!("The tasks of a script with a DOCKER or SLURM table cannot be run on --hosts");
+            exit(-1);
+        }
+        Some(executor)
+            if executor.batch().is_some() && (args.compress || args.max_output_bytes.is_some()) =>
+        {
+            error!("--compress and --max-output-bytes cannot be used with SLURM, the jobs write their outputs themselves");
+            exit(-1);
+        }
+        Some(executor) => runner.set_executor(executor),
+        None if !args.hosts.is_empty() => {
+            info!("tasks run on: {}", args.hosts.join(", "));
+            runner.set_executor(Box::new(SshExecutor::new(args.hosts)));
+        }
+        None => {
+            let cores = args.cpu_affinity.unwrap_or_default();
+            if !cores.is_empty() && cores.len() < num_cores {
+                warn!(
+                    "{} workers pinned to {} cores, some tasks share a core",
+                    num_cores,
+                    cores.len()
+                );
+            }
+            runner.set_executor(Box::new(LocalExecutor::new(args.nice, cores)));
+        }
+    }
+    if !args.no_preflight && !remote {
+        if let Err(errors) = runner.preflight() {
+            for e in &errors {
+                error!("{}", e);
+            }
+            error!("Some programs cannot be run, Aborting...");
+            exit(-1);
+        }
+    }
+    if args.resume && results_file.exists() {
+        match read_results(&results_file) {
+            Ok(previous) => runner.set_previous_results(&previous),
+            Err(e) => {
+                error!("Cannot read the previous results {:?}: {}", results_file, e);
+                exit(-1);
+            }
+        }
+    }
+    if let Some(csv) = args.csv {
+        match CsvWriter::create(&csv) {
+            Ok(writer) => runner.set_csv(writer),
+            Err(e) => {
+                error!("Cannot create the CSV file {:?}: {}", csv, e);
+                exit(-1);
+            }
+        }
+    }
+    match &args.events_file {
+        None => {}
+        Some(_) if events_on_stderr => runner.set_events(EventWriter::stderr()),
+        Some(path) => match EventWriter::create(path) {
+            Ok(writer) => runner.set_events(writer),
+            Err(e) => {
+                error!("Cannot create the events file {:?}: {}", path, e);
+                exit(-1);
+            }
+        },
+    }
+    runner.set_status(StatusFile::new(status_file));
+    let stop = runner.stop_flag();
+    if let Err(e) = ctrlc::set_handler(move || {
+        warn!("Interrupted, killing the running tasks...");
+        stop.store(true, Ordering::SeqCst);
+    }) {
+        error!("Cannot install the Ctrl-C handler: {}", e);
+    }
+    let start = Instant::now();
+    let results = runner.run(num_cores);
+    // exit before writing the results, not to overwrite the ones of a previous run
+    if runner.hook_failed() {
+        exit(-1);
+    }
+    log_summary(&results, start.elapsed(), args.group_by.as_deref());
+    match write_results(&results_file, &results) {
+        Ok(()) => info!("Results written to {:?}", results_file),
+        Err(e) => {
+            error!("Cannot write the results to {:?}: {}", results_file, e);
+            exit(-1);
+        }
+    }
+    if runner.failed_fast() {
+        error!("Run stopped after a task failed, the tasks not started are not in the results");
+        exit(EXIT_FAILED_FAST);
+    }
+    if runner.max_failures_reached() {
+        error!(
+            "Run stopped after too many tasks failed, the tasks not started are not in the results"
+        );
+        exit(EXIT_FAILED_FAST);
+    }
+    if runner.out_of_time() {
+        let not_run = results.iter().filter(|r| r.not_started()).count();
+        warn!(
+            "Run stopped after its maximum runtime, {} tasks were not run",
+            not_run
+        );
+        return;
+    }
+    if runner.stopped() {
+        error!("Run interrupted, the tasks not started are not in the results");
+        exit(EXIT_INTERRUPTED);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// the result of a task name run once, which exited with exit_code
+    fn result(name: &str, exit_code: Option<i32>) -> TaskResult {
+        TaskResult {
+            name: name.to_string(),
+            command: "true".to_string(),
+            exit_code,
+            attempts: 1,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_filter_tasks() {
+        let tasks: Vec<Task> = ["fft", "fft_large", "md5", "sha"]
+            .iter()
+            .map(|name| Task::builder().name(*name).cmd("true").build().unwrap())
+            .collect();
+        let names = |tasks: Vec<Task>| tasks.into_iter().map(|t| t.name).collect::<Vec<_>>();
+        let fft = Regex::new("fft").unwrap();
+        let large = Regex::new("large$").unwrap();
+
+        assert_eq!(
+            names(filter_tasks(tasks.clone(), Some(&fft), None)),
+            ["fft", "fft_large"]
+        );
+        assert_eq!(
+            names(filter_tasks(tasks.clone(), Some(&fft), Some(&large))),
+            ["fft"]
+        );
+        assert_eq!(names(filter_tasks(tasks, None, Some(&fft))), ["md5", "sha"]);
+    }
+
+    #[test]
+    fn test_repeat_tasks() {
+        let task = |name: &str| Task::builder().name(name).cmd("true").build().unwrap();
+        let tasks = repeat_tasks(vec![task("fft"), task("crc")], 3);
+        let names: Vec<&str> = tasks.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(
+            names,
+            ["fft#1", "fft#2", "fft#3", "crc#1", "crc#2", "crc#3"]
+        );
+        assert_eq!(tasks[4].labels["repeat"], "2");
+        let tasks = repeat_tasks(
+            vec![Task::builder().cmd("true").warmup().build().unwrap()],
+            2,
+        );
+        assert!(tasks[0].warmup);
+        assert!(!tasks[1].warmup);
+        assert_eq!(repeat_tasks(vec![task("fft")], 1)[0].name, "fft");
+    }
+
+    #[test]
+    fn test_select_failed() {
+        let task = |name: &str| Task::builder().name(name).cmd("true").build().unwrap();
+        let result = |task: &Task, exit_code, timed_out| TaskResult {
+            timed_out,
+            ..result(&task.name, exit_code)
+        };
+        let tasks = vec![task("fft"), task("md5"), task("crc"), task("sha")];
+        let mut repeated = result(&task("sha#2"), Some(1), false);
+        repeated
+            .labels
+            .insert("repeat".to_string(), "2".to_string());
+        let previous = [
+            result(&tasks[0], Some(0), false),
+            result(&tasks[1], Some(1), false),
+            result(&tasks[2], None, true),
+            repeated,
+        ];
+        let selected = select_failed(tasks.clone(), &previous).unwrap();
+        let names: Vec<&str> = selected.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(names, ["md5", "crc", "sha"]);
+        let err = select_failed(vec![task("fft")], &previous).err().unwrap();
+        assert!(err.contains("crc, md5, sha"));
+    }
+
+    #[test]
+    fn test_seed_weights() {
+        let task = |name: &str| Task::builder().name(name).cmd("true").build().unwrap();
+        let result = |name: &str, secs, repeat: Option<&str>| TaskResult {
+            duration: Duration::from_secs(secs),
+            labels: repeat
+                .map(|k| BTreeMap::from([("repeat".to_string(), k.to_string())]))
+                .unwrap_or_default(),
+            ..result(name, Some(0))
+        };
+        let mut tasks = vec![task("fft"), task("md5"), task("new")];
+        tasks[2].weight = Some(7.0);
+        let mut skipped = result("md5", 0, None);
+        skipped.skipped = true;
+        skipped.attempts = 0;
+        // dropped at the maximum runtime
+        let not_run = |name| TaskResult {
+            attempts: 0,
+            ..result(name, 0, None)
+        };
+        let previous = [
+            result("fft#1", 10, Some("1")),
+            result("fft#2", 20, Some("2")),
+            result("md5", 3, None),
+            skipped,
+            not_run("md5"),
+            not_run("new"),
+        ];
+        assert_eq!(seed_weights(&mut tasks, &previous), 2);
+        let weights: Vec<Option<f64>> = tasks.iter().map(|t| t.weight).collect();
+        assert_eq!(weights, [Some(15.0), Some(3.0), Some(7.0)]);
+    }
+
+    #[test]
+    fn test_append_args() {
+        let mut tasks = vec![Task::builder().cmd("tool").arg("a").build().unwrap()];
+        let extra = ["--extra-prop".to_string(), "foo".to_string()];
+        append_args(&mut tasks, &extra).unwrap();
+        assert_eq!(tasks[0].args, ["a", "--extra-prop", "foo"]);
+        let err = append_args(&mut tasks, &["$x".to_string()]).unwrap_err();
+        assert!(err.contains("unresolved variables: $x"));
+        let args = Args::try_parse_from(["zexp", "-s", "x.toml", "--", "--extra-prop", "foo"]);
+        assert_eq!(args.unwrap().extra_args, extra);
+    }
+
+    #[test]
+    fn test_commands() {
+        let parse = |argv: &[&str]| {
+            let mut args = Args::try_parse_from(argv)?;
+            args.apply_command()?;
+            Ok::<Args, clap::Error>(args)
+        };
+        let args = parse(&["zexp", "-s", "x.toml"]).unwrap();
+        assert!(args.command.is_none() && !args.list && !args.check);
+        let args = parse(&["zexp", "run", "-s", "x.toml", "-j", "4", "--", "--extra"]).unwrap();
+        assert_eq!(args.common.script.as_deref(), Some("x.toml"));
+        assert_eq!(args.common.j, 4);
+        assert_eq!(args.extra_args, ["--extra"]);
+        // the options go before or after the command
+        let args = parse(&["zexp", "-s", "x.toml", "list", "--cmds"]).unwrap();
+        assert!(args.list && args.list_cmds);
+        let args = parse(&["zexp", "list", "-s", "x.toml"]).unwrap();
+        assert!(args.list && !args.list_cmds);
+        assert!(parse(&["zexp", "check", "-s", "x.toml"]).unwrap().check);
+        assert!(parse(&["zexp", "validate", "-s", "x.toml"]).unwrap().check);
+        assert!(
+            parse(&["zexp", "config", "-s", "x.toml"])
+                .unwrap()
+                .print_config
+        );
+        let args = parse(&["zexp", "tasks", "emit", "t.json", "-s", "x.toml"]).unwrap();
+        assert_eq!(args.emit_tasks, Some(PathBuf::from("t.json")));
+        assert!(args.emit_only());
+        let args = parse(&["zexp", "--emit-tasks", "t.json", "-s", "x.toml"]).unwrap();
+        assert!(!args.emit_only());
+        let args = parse(&["zexp", "tasks", "run", "t.json", "--out-dir", "out"]).unwrap();
+        assert_eq!(args.run_tasks, Some(PathBuf::from("t.json")));
+        assert_eq!(args.out_dir, PathBuf::from("out"));
+        assert!(parse(&["zexp", "tasks", "run", "t.json", "-s", "x.toml"]).is_err());
+        assert!(parse(&["zexp", "tasks"]).is_err());
+    }
+
+    #[test]
+    fn test_shuffle_tasks() {
+        let tasks: Vec<Task> = (0..20)
+            .map(|i| {
+                Task::builder()
+                    .name(format!("t{}", i))
+                    .cmd("true")
+                    .build()
+                    .unwrap()
+            })
+            .collect();
+        let names = |tasks: &[Task]| tasks.iter().map(|t| t.name.clone()).collect::<Vec<_>>();
+        let shuffled = |seed| {
+            let mut tasks = tasks.clone();
+            shuffle_tasks(&mut tasks, seed);
+            names(&tasks)
+        };
+        // the same seed gives the same order
+        assert_eq!(shuffled(42), shuffled(42));
+        assert_ne!(shuffled(42), names(&tasks));
+        let mut sorted = shuffled(42);
+        sorted.sort();
+        let mut all = names(&tasks);
+        all.sort();
+        assert_eq!(sorted, all);
+    }
+
+    #[test]
+    fn test_parse_log_level() {
+        assert_eq!(parse_log_level("off"), Ok(LevelFilter::Off));
+        assert_eq!(parse_log_level("error"), Ok(LevelFilter::Error));
+        assert_eq!(parse_log_level("warn"), Ok(LevelFilter::Warn));
+        assert_eq!(parse_log_level("info"), Ok(LevelFilter::Info));
+        assert_eq!(parse_log_level("debug"), Ok(LevelFilter::Debug));
+        assert_eq!(parse_log_level("trace"), Ok(LevelFilter::Trace));
+        assert_eq!(parse_log_level("DEBUG"), Ok(LevelFilter::Debug));
+        assert!(parse_log_level("verbose").is_err());
+
+        let args = Args::try_parse_from(["zexp", "-s", "x.toml"]).unwrap();
+        assert_eq!(args.common.log_level, LevelFilter::Info);
+        let args = Args::try_parse_from(["zexp", "-s", "x.toml", "--log-level", "warn"]).unwrap();
+        assert_eq!(args.common.log_level, LevelFilter::Warn);
+        assert!(Args::try_parse_from(["zexp", "-s", "x.toml", "--log-level", "loud"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_cores() {
+        assert_eq!(parse_cores("0-3,8"), Ok(vec![0, 1, 2, 3, 8]));
+        assert_eq!(parse_cores("2"), Ok(vec![2]));
+        assert!(parse_cores("a").is_err());
+        assert!(parse_cores("1,").is_err());
+        let args = Args::try_parse_from(["zexp", "--nice", "-5"]).unwrap();
+        assert_eq!(args.nice, Some(-5));
+        assert!(Args::try_parse_from(["zexp", "--nice", "20"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_timeout() {
+        assert_eq!(parse_timeout("90"), Ok(90));
+        assert_eq!(parse_timeout("2h"), Ok(7200));
+        assert!(parse_timeout("2x").is_err());
+        let args = Args::try_parse_from(["zexp", "-s", "x.toml", "--timeout", "30m"]).unwrap();
+        assert_eq!(args.timeout, Some(1800));
+    }
+
+    #[test]
+    fn test_parse_jobs() {
+        assert_eq!(parse_jobs("4"), Ok(4));
+        assert_eq!(parse_jobs("auto"), Ok(0));
+        assert!(parse_jobs("-1").is_err());
+        assert!(parse_jobs("all").is_err());
+        assert_eq!(resolve_jobs(3, 8), 3);
+        assert_eq!(resolve_jobs(0, 8), 8);
+        assert!(available_cores() >= 1);
+        let args = Args::try_parse_from(["zexp", "-s", "x.toml", "-j", "auto"]).unwrap();
+        assert_eq!(args.common.j, 0);
+    }
+
+    #[test]
+    fn test_oversubscription_warning() {
+        assert_eq!(oversubscription_warning(8, 8), None);
+        assert_eq!(oversubscription_warning(1, 8), None);
+        let warning = oversubscription_warning(64, 8).unwrap();
+        assert!(warning.contains("-j 8"), "{}", warning);
+    }
+
+    #[test]
+    fn test_check_tasks() {
+        let task = |name: &str, cmd: &str| Task::builder().name(name).cmd(cmd).build().unwrap();
+        assert!(check_tasks(&[task("ok", "true")]).is_empty());
+        assert_eq!(check_tasks(&[]).len(), 1);
+        let problems = check_tasks(&[task("ok", "true"), task("ko", "zexp-no-such-program")]);
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].contains("zexp-no-such-program"));
+    }
+
+    #[test]
+    fn test_speedup() {
+        let result = |secs| TaskResult {
+            duration: Duration::from_secs(secs),
+            ..result("fft", Some(0))
+        };
+        let results = [result(3), result(5)];
+        let (task_time, factor) = speedup(&results, Duration::from_secs(4));
+        assert_eq!(task_time, Duration::from_secs(8));
+        assert_eq!(factor, 2.0);
+        assert_eq!(speedup(&[], Duration::ZERO).1, 1.0);
+    }
+
+    #[test]
+    fn test_scope_line() {
+        let task = |name: &str, benchset: Option<&str>| Task {
+            benchset: benchset.map(String::from),
+            ..Task::builder().name(name).cmd("true").build().unwrap()
+        };
+        assert_eq!(
+            scope_line(&[task("a", None), task("b", None)]),
+            "Generated 2 tasks"
+        );
+        let tasks = [
+            task("fft", Some("kernel")),
+            task("md5", Some("kernel")),
+            task("lift", Some("app")),
+        ];
+        assert_eq!(
+            scope_line(&tasks),
+            "Generated 3 tasks across 2 benchsets (app: 1, kernel: 2)"
+        );
+    }
+
+    #[test]
+    fn test_group_counts() {
+        let result = |mode: Option<&str>, exit_code| TaskResult {
+            labels: mode
+                .map(|mode| BTreeMap::from([("mode".to_string(), mode.to_string())]))
+                .unwrap_or_default(),
+            ..result("fft", Some(exit_code))
+        };
+        let results = [
+            result(Some("a"), 0),
+            result(Some("a"), 1),
+            result(Some("b"), 0),
+            result(None, 0),
+        ];
+        let counts = group_counts(&results, "mode");
+        assert_eq!(counts["a"], (1, 2));
+        assert_eq!(counts["b"], (1, 1));
+        assert_eq!(counts["-"], (1, 1));
+    }
+
+    #[test]
+    fn test_list_lines() {
+        let tasks = [Task::builder()
+            .name("fft")
+            .cmd("otawa")
+            .args(["fft.elf", "a b"])
+            .build()
+            .unwrap()];
+        assert_eq!(list_lines(&tasks, false), ["fft"]);
+        assert_eq!(list_lines(&tasks, true), ["fft\totawa fft.elf 'a b'"]);
+        let tasks = [Task::builder()
+            .name("fft")
+            .cmd("otawa")
+            .description("Fast Fourier Transform")
+            .build()
+            .unwrap()];
+        assert_eq!(list_lines(&tasks, false), ["fft\tFast Fourier Transform"]);
+        assert_eq!(
+            list_lines(&tasks, true),
+            ["fft\totawa\tFast Fourier Transform"]
+        );
+    }
 }
@@ -0,0 +1,208 @@
+use crate::runner::TaskResult;
+use indicatif::{HumanDuration, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use log::{Log, Metadata, Record};
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// A progress bar of the tasks of a run, with an ETA from the mean duration of the tasks done
+pub struct Progress {
+    bar: ProgressBar,
+    num_cores: usize,
+    /// number of tasks run and their total duration, the skipped ones are not counted
+    /// so they do not lower the mean
+    run: Mutex<(u32, Duration)>,
+}
+
+impl Progress {
+    pub fn new(bar: ProgressBar, num_cores: usize) -> Self {
+        bar.set_style(
+            ProgressStyle::with_template("[{elapsed_precise}] {wide_bar} {pos}/{len} {msg}")
+                .unwrap(),
+        );
+        Self {
+            bar,
+            num_cores,
+            run: Mutex::new((0, Duration::ZERO)),
+        }
+    }
+
+    /// show the bar for total tasks, it is not drawn when stderr is not a terminal
+    pub fn start(&self, total: usize) {
+        self.bar.set_length(total as u64);
+        self.bar.set_position(0);
+        self.bar.set_draw_target(ProgressDrawTarget::stderr());
+    }
+
+    /// count a task as done and update the ETA
+    pub fn task_done(&self, result: &TaskResult) {
+        let mut run = self.run.lock().unwrap();
+        if !result.skipped {
+            run.0 += 1;
+            run.1 += result.duration;
+        }
+        self.bar.inc(1);
+        let remaining = self.bar.length().unwrap_or(0) - self.bar.position();
+        if run.0 > 0 {
+            let eta = eta(run.1 / run.0, remaining, self.num_cores);
+            self.bar.set_message(format!("ETA {}", HumanDuration(eta)));
+        }
+    }
+
+    /// remove the bar, so that it is not drawn again below the next logs
+    pub fn finish(&self) {
+        self.bar.finish_and_clear();
+    }
+}
+
+/// the time to run remaining tasks of mean duration on num_cores workers
+fn eta(mean: Duration, remaining: u64, num_cores: usize) -> Duration {
+    let rounds = remaining.div_ceil(num_cores.max(1) as u64);
+    mean * rounds as u32
+}
+
+/// The counts of the tasks of a run, in the status file
+#[derive(Debug, Default, Serialize)]
+struct Status {
+    pending: usize,
+    running: usize,
+    /// the tasks that terminated, failed or not
+    done: usize,
+    failed: usize,
+    /// the names of the running tasks
+    running_tasks: BTreeSet<String>,
+}
+
+/// A status file of a run, rewritten whenever a task starts or terminates, for a monitor to poll,
+/// e.g. `{"pending":3,"running":1,"done":5,"failed":1,"running_tasks":["fft"]}`
+pub struct StatusFile {
+    path: PathBuf,
+    status: Mutex<Status>,
+}
+
+impl StatusFile {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            status: Mutex::new(Status::default()),
+        }
+    }
+
+    /// all the total tasks are pending
+    pub fn start(&self, total: usize) -> io::Result<()> {
+        let mut status = self.status.lock().unwrap();
+        *status = Status {
+            pending: total,
+            ..Status::default()
+        };
+        self.write(&status)
+    }
+
+    /// the task name is started
+    pub fn task_started(&self, name: &str) -> io::Result<()> {
+        let mut status = self.status.lock().unwrap();
+        status.pending = status.pending.saturating_sub(1);
+        status.running_tasks.insert(name.to_string());
+        status.running = status.running_tasks.len();
+        self.write(&status)
+    }
+
+    /// the task of result is done, it may not have been started if it was skipped
+    pub fn task_done(&self, result: &TaskResult) -> io::Result<()> {
+        let mut status = self.status.lock().unwrap();
+        if status.running_tasks.remove(&result.name) {
+            status.running = status.running_tasks.len();
+        } else {
+            status.pending = status.pending.saturating_sub(1);
+        }
+        status.done += 1;
+        if !result.passed() && !result.skipped && !result.not_started() {
+            status.failed += 1;
+        }
+        self.write(&status)
+    }
+
+    /// write status to a temporary file renamed to the status file, so that the status file
+    /// is never seen half written
+    fn write(&self, status: &Status) -> io::Result<()> {
+        let tmp = self.path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_vec(status)?)?;
+        fs::rename(&tmp, &self.path)
+    }
+}
+
+/// A logger hiding the progress bar while it writes, so that the logs are printed above it
+pub struct ProgressLogger {
+    inner: Box<dyn Log>,
+    bar: ProgressBar,
+}
+
+impl ProgressLogger {
+    pub fn new(inner: Box<dyn Log>, bar: ProgressBar) -> Self {
+        Self { inner, bar }
+    }
+}
+
+impl Log for ProgressLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if self.inner.enabled(record.metadata()) {
+            self.bar.suspend(|| self.inner.log(record));
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_status_file() {
+        let dir = std::env::temp_dir().join("zexp-test-status-file");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("status.json");
+        let read = || -> serde_json::Value {
+            serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap()
+        };
+        let result = |name: &str, exit_code| TaskResult {
+            name: name.to_string(),
+            exit_code: Some(exit_code),
+            attempts: 1,
+            ..Default::default()
+        };
+        let status = StatusFile::new(path.clone());
+        status.start(3).unwrap();
+        assert_eq!(read()["pending"], 3);
+        status.task_started("fft").unwrap();
+        status.task_started("md5").unwrap();
+        assert_eq!(read()["running"], 2);
+        assert_eq!(read()["running_tasks"], serde_json::json!(["fft", "md5"]));
+        status.task_done(&result("fft", 1)).unwrap();
+        let json = read();
+        assert_eq!(json["pending"], 1);
+        assert_eq!(json["running_tasks"], serde_json::json!(["md5"]));
+        assert_eq!(json["done"], 1);
+        assert_eq!(json["failed"], 1);
+        assert!(!dir.join("status.json.tmp").exists());
+    }
+
+    #[test]
+    fn test_eta() {
+        let mean = Duration::from_secs(10);
+        assert_eq!(eta(mean, 0, 4), Duration::ZERO);
+        assert_eq!(eta(mean, 3, 4), Duration::from_secs(10));
+        assert_eq!(eta(mean, 9, 4), Duration::from_secs(30));
+        assert_eq!(eta(mean, 2, 1), Duration::from_secs(20));
+    }
+}
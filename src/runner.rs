@@ -0,0 +1,2464 @@
+use crate::executor::{BatchSystem, Executor, LocalExecutor};
+use crate::progress::{Progress, StatusFile};
+use crate::script::{Hooks, Task};
+use crate::sys;
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use indicatif::HumanDuration;
+use log::{debug, error, info, warn};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::{BTreeMap, HashSet, VecDeque};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::io::{BufRead, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ExitStatus, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use wait_timeout::ChildExt;
+
+/// Options shared by all the tasks of a run
+pub struct RunOptions {
+    /// timeout used for the tasks that do not specify one
+    pub default_timeout: Option<u64>,
+    pub out_dir: PathBuf,
+    pub overwrite: bool,
+    pub merge_output: bool,
+    /// number of reruns of a failing task that does not specify it
+    pub retries: u32,
+    /// also rerun the tasks that timed out
+    pub retry_on_timeout: bool,
+    /// skip the tasks that are already done, see `Runner::is_done`
+    pub resume: bool,
+    /// memory limit in MB of the tasks that do not specify one, on Linux only
+    pub mem_limit_mb: Option<u64>,
+    /// stop the run as soon as a task fails or times out
+    pub fail_fast: bool,
+    /// stop the run once this many tasks failed or timed out, see `Runner::max_failures_reached`
+    pub max_failures: Option<u32>,
+    /// write a header line with the command line at the top of the output files, see `header`
+    pub header: bool,
+    /// minimum delay between the starts of two tasks, so that the load ramps up gradually
+    pub stagger: Option<Duration>,
+    /// write the outputs through gzip, to `<name>.out.gz` or `<name>.stdout.gz` and
+    /// `<name>.stderr.gz`
+    pub compress: bool,
+    /// maximum duration of the run, no task is started after it, and with `fail_fast` the
+    /// running ones are killed, see `Runner::out_of_time`
+    pub max_runtime: Option<Duration>,
+    /// maximum size of each output file, without the header, the output after it is dropped
+    pub max_output_bytes: Option<u64>,
+    /// kill the tasks whose output exceeds `max_output_bytes`, instead of letting them finish
+    pub kill_on_max_output: bool,
+    /// log this many last lines of the outputs of each task that fails or times out
+    pub tail: Option<usize>,
+}
+
+/// the options of the binary when none is given: the outputs in `./zexp-out`, separate and with
+/// a header, no timeout, retry nor limit
+impl Default for RunOptions {
+    fn default() -> Self {
+        Self {
+            default_timeout: None,
+            out_dir: PathBuf::from("./zexp-out"),
+            overwrite: false,
+            merge_output: false,
+            retries: 0,
+            retry_on_timeout: false,
+            resume: false,
+            mem_limit_mb: None,
+            fail_fast: false,
+            max_failures: None,
+            header: true,
+            stagger: None,
+            compress: false,
+            max_runtime: None,
+            max_output_bytes: None,
+            kill_on_max_output: false,
+            tail: None,
+        }
+    }
+}
+
+/// The outcome of one task
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TaskResult {
+    pub name: String,
+    /// the command line of the task, as given by `Task::command_line`
+    pub command: String,
+    /// exit code of the task, `None` if it was killed (by a signal or after a timeout)
+    pub exit_code: Option<i32>,
+    pub timed_out: bool,
+    /// wall-clock duration, in seconds in the results file
+    #[serde(
+        serialize_with = "serialize_secs",
+        deserialize_with = "deserialize_secs"
+    )]
+    pub duration: Duration,
+    /// the files the output of the last attempt was written to
+    pub outputs: Vec<PathBuf>,
+    /// number of times the task was run
+    #[serde(default)]
+    pub attempts: u32,
+    /// the task was not run because it was already done
+    #[serde(default)]
+    pub skipped: bool,
+    /// the task was killed because the run was stopped, e.g. by Ctrl-C
+    #[serde(default)]
+    pub interrupted: bool,
+    /// a hint on why the task failed, e.g. it may have exceeded its memory limit
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    /// the labels of the task
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
+    /// the compression of the output files, "gzip" or none
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compression: Option<String>,
+    /// peak resident memory in kB of the last attempt, sampled while it runs, on Linux only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub peak_rss_kb: Option<u64>,
+    /// how the last attempt terminated, `None` if the task was not run, or if its job was
+    /// cancelled because the run was stopped
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub outcome: Option<TaskOutcome>,
+    /// the id of the job the last attempt was submitted as, with a batch system
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub job_id: Option<String>,
+}
+
+/// How a task terminated, e.g. to tell a crash of its program from a clean failure,
+/// `{"non_zero":1}` or `{"signaled":11}` in the results file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TaskOutcome {
+    /// exited with code 0
+    Ok,
+    /// exited with this other code
+    NonZero(i32),
+    /// killed after its timeout
+    TimedOut,
+    /// terminated by this signal, e.g. 11 for a segmentation fault on Linux, on Unix only
+    Signaled(i32),
+    /// could not be started, with why, e.g. `cannot be started: No such file or directory`
+    SpawnFailed(String),
+}
+
+impl TaskOutcome {
+    /// the outcome of a process that terminated with status
+    fn from_status(status: ExitStatus) -> Self {
+        match (status.code(), sys::exit_signal(status)) {
+            (Some(0), _) => TaskOutcome::Ok,
+            (Some(code), _) => TaskOutcome::NonZero(code),
+            (None, Some(sig)) => TaskOutcome::Signaled(sig),
+            // neither an exit code nor a signal, which no supported platform reports
+            (None, None) => TaskOutcome::NonZero(-1),
+        }
+    }
+}
+
+impl fmt::Display for TaskOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TaskOutcome::Ok => write!(f, "exited with code 0"),
+            TaskOutcome::NonZero(code) => write!(f, "exited with code {}", code),
+            TaskOutcome::TimedOut => write!(f, "timed out"),
+            TaskOutcome::Signaled(sig) => match sys::signal_name(*sig) {
+                Some(name) => write!(f, "killed by signal {} ({})", sig, name),
+                None => write!(f, "killed by signal {}", sig),
+            },
+            TaskOutcome::SpawnFailed(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+fn serialize_secs<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(duration.as_secs_f64())
+}
+
+fn deserialize_secs<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+    Ok(Duration::from_secs_f64(f64::deserialize(deserializer)?))
+}
+
+impl TaskResult {
+    pub fn passed(&self) -> bool {
+        !self.skipped && !self.timed_out && !self.interrupted && self.exit_code == Some(0)
+    }
+
+    /// whether the task was not run though it was not done, e.g. because the maximum runtime
+    /// was reached, which is not a failure of the task
+    pub fn not_started(&self) -> bool {
+        !self.skipped && self.attempts == 0
+    }
+
+    /// the result of a task that is not run because it is already done
+    fn skipped(task: &Task, outputs: Vec<PathBuf>) -> Self {
+        Self {
+            name: task.name.clone(),
+            command: task.command_line(),
+            exit_code: None,
+            timed_out: false,
+            duration: Duration::ZERO,
+            compression: compression(&outputs),
+            outputs,
+            attempts: 0,
+            skipped: true,
+            interrupted: false,
+            note: None,
+            labels: task.labels.clone(),
+            peak_rss_kb: None,
+            outcome: None,
+            job_id: None,
+        }
+    }
+
+    /// the result of a task that could not be started, with the error in note
+    fn error(task: &Task, outputs: Vec<PathBuf>, duration: Duration, note: String) -> Self {
+        error!("Task {} {}", task.name, note);
+        Self {
+            duration,
+            compression: compression(&outputs),
+            outputs,
+            attempts: 1,
+            outcome: Some(TaskOutcome::SpawnFailed(note.clone())),
+            ..Self::not_run(task, note)
+        }
+    }
+
+    /// the result of a task that is not run, with the reason why in note
+    fn not_run(task: &Task, note: String) -> Self {
+        Self {
+            skipped: false,
+            note: Some(note),
+            ..Self::skipped(task, Vec::new())
+        }
+    }
+}
+
+/// the compression of the output files, from their extension
+fn compression(outputs: &[PathBuf]) -> Option<String> {
+    outputs
+        .iter()
+        .any(|path| path.extension().is_some_and(|ext| ext == "gz"))
+        .then(|| "gzip".to_string())
+}
+
+/// read the results written by `write_results`
+pub fn read_results(path: &Path) -> io::Result<Vec<TaskResult>> {
+    let file = fs::File::open(path)?;
+    Ok(serde_json::from_reader(file)?)
+}
+
+/// write the results of a run to `path`, in JSON
+pub fn write_results(path: &Path, results: &[TaskResult]) -> io::Result<()> {
+    let file = fs::File::create(path)?;
+    serde_json::to_writer_pretty(file, results)?;
+    Ok(())
+}
+
+/// Write the results to a CSV file, one row per task as soon as it terminates
+pub struct CsvWriter {
+    file: fs::File,
+}
+
+impl CsvWriter {
+    /// create the CSV file and write its header
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let mut file = fs::File::create(path)?;
+        writeln!(
+            file,
+            "name,exit_code,timed_out,duration_secs,peak_rss_kb,labels"
+        )?;
+        file.flush()?;
+        Ok(Self { file })
+    }
+
+    /// write the row of a result, flushed so that it survives an interrupted run,
+    /// the labels are written as `key=value` separated by `;`
+    pub fn write(&mut self, result: &TaskResult) -> io::Result<()> {
+        let exit_code = result
+            .exit_code
+            .map_or(String::new(), |code| code.to_string());
+        let peak_rss_kb = result
+            .peak_rss_kb
+            .map_or(String::new(), |kb| kb.to_string());
+        let labels: Vec<String> = result
+            .labels
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, value))
+            .collect();
+        writeln!(
+            self.file,
+            "{},{},{},{},{},{}",
+            csv_quote(&result.name),
+            exit_code,
+            result.timed_out,
+            result.duration.as_secs_f64(),
+            peak_rss_kb,
+            csv_quote(&labels.join(";"))
+        )?;
+        self.file.flush()
+    }
+}
+
+/// quote a CSV field if it contains a separator, a quote or a line break
+fn csv_quote(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// An event written by `EventWriter`
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "lowercase")]
+enum Event<'a> {
+    Start {
+        name: &'a str,
+    },
+    Finish {
+        name: &'a str,
+        code: Option<i32>,
+        /// in seconds
+        duration: f64,
+        passed: bool,
+        skipped: bool,
+    },
+}
+
+/// Write the events of a run in JSON lines, one object per line as soon as a task starts
+/// or terminates, so that a monitor can follow the run:
+/// `{"event":"start","name":...}` and
+/// `{"event":"finish","name":...,"code":...,"duration":...,"passed":...,"skipped":...}`
+pub struct EventWriter {
+    out: Box<dyn Write + Send>,
+}
+
+impl EventWriter {
+    /// write the events to the file at path
+    pub fn create(path: &Path) -> io::Result<Self> {
+        Ok(Self {
+            out: Box::new(fs::File::create(path)?),
+        })
+    }
+
+    /// write the events to stderr
+    pub fn stderr() -> Self {
+        Self {
+            out: Box::new(io::stderr()),
+        }
+    }
+
+    /// write an event on its own line, flushed so that it is seen at once
+    fn write(&mut self, event: Event) -> io::Result<()> {
+        serde_json::to_writer(&mut self.out, &event)?;
+        writeln!(self.out)?;
+        self.out.flush()
+    }
+
+    /// write the start of task
+    pub fn start(&mut self, task: &Task) -> io::Result<()> {
+        self.write(Event::Start { name: &task.name })
+    }
+
+    /// write the end of the task of result
+    pub fn finish(&mut self, result: &TaskResult) -> io::Result<()> {
+        self.write(Event::Finish {
+            name: &result.name,
+            code: result.exit_code,
+            duration: result.duration.as_secs_f64(),
+            passed: result.passed(),
+            skipped: result.skipped,
+        })
+    }
+}
+
+/// start of the header line of the output files
+const HEADER_PREFIX: &str = "# zexp: ";
+
+/// the header line of the output files of task: its command line, when and where it is run
+fn header(task: &Task) -> String {
+    let now = time::OffsetDateTime::now_utc()
+        .replace_nanosecond(0)
+        .unwrap()
+        .format(&time::format_description::well_known::Rfc3339)
+        .unwrap();
+    let host = gethostname::gethostname();
+    format!(
+        "{}{} (at {} on {})\n",
+        HEADER_PREFIX,
+        task.command_line(),
+        now,
+        host.to_string_lossy()
+    )
+}
+
+/// whether the output file at path has some output of its task, after the header line if any
+fn has_output(path: &Path) -> bool {
+    let Ok(mut reader) = open_output(path) else {
+        return false;
+    };
+    let mut first_line = String::new();
+    // a first line that is not valid UTF-8 is not a header
+    if reader.read_line(&mut first_line).is_err() {
+        return true;
+    }
+    if !first_line.starts_with(HEADER_PREFIX) {
+        return !first_line.is_empty();
+    }
+    reader.fill_buf().is_ok_and(|rest| !rest.is_empty())
+}
+
+/// open the output file at path for reading, decompressed if it is gzipped
+fn open_output(path: &Path) -> io::Result<io::BufReader<Box<dyn Read>>> {
+    let file = fs::File::open(path)?;
+    // a marker may be appended in a gzip member of its own, see `append_output`
+    let file: Box<dyn Read> = if path.extension().is_some_and(|ext| ext == "gz") {
+        Box::new(MultiGzDecoder::new(file))
+    } else {
+        Box::new(file)
+    };
+    Ok(io::BufReader::new(file))
+}
+
+/// the last n lines of the output file at path, without its header line if any
+fn tail_lines(path: &Path, n: usize) -> io::Result<Vec<String>> {
+    let mut reader = open_output(path)?;
+    let mut lines = VecDeque::with_capacity(n + 1);
+    let mut line = Vec::new();
+    let mut first = true;
+    while reader.read_until(b'\n', &mut line)? > 0 {
+        // the output of a task is not always valid UTF-8
+        let text = String::from_utf8_lossy(&line)
+            .trim_end_matches(['\n', '\r'])
+            .to_string();
+        if !(first && text.starts_with(HEADER_PREFIX)) {
+            lines.push_back(text);
+            if lines.len() > n {
+                lines.pop_front();
+            }
+        }
+        first = false;
+        line.clear();
+    }
+    Ok(lines.into())
+}
+
+/// append text to the output file at path, in a gzip member of its own if it is gzipped
+fn append_output(path: &Path, text: &str) -> io::Result<()> {
+    let file = fs::OpenOptions::new().append(true).open(path)?;
+    if path.extension().is_some_and(|ext| ext == "gz") {
+        let mut encoder = GzEncoder::new(file, Compression::default());
+        encoder.write_all(text.as_bytes())?;
+        encoder.finish()?;
+        Ok(())
+    } else {
+        (&file).write_all(text.as_bytes())
+    }
+}
+
+/// the line appended to the outputs of a task killed after its timeout of secs seconds,
+/// so that they do not seem to end for no reason
+fn timeout_marker(secs: u64) -> String {
+    format!("\n=== ZEXP: task timed out after {}s, killed ===\n", secs)
+}
+
+/// written at the end of an output file truncated by `RunOptions::max_output_bytes`
+const TRUNCATED_MARKER: &str = "\n...truncated...\n";
+
+/// The end of an output file a task writes to
+enum Sink {
+    File(fs::File),
+    /// a pipe to a thread copying what it reads into the file, see `pipe_sink`
+    Pipe(io::PipeWriter),
+}
+
+impl Sink {
+    fn try_clone(&self) -> io::Result<Self> {
+        match self {
+            Sink::File(file) => file.try_clone().map(Sink::File),
+            Sink::Pipe(pipe) => pipe.try_clone().map(Sink::Pipe),
+        }
+    }
+}
+
+impl From<Sink> for Stdio {
+    fn from(sink: Sink) -> Self {
+        match sink {
+            Sink::File(file) => file.into(),
+            Sink::Pipe(pipe) => pipe.into(),
+        }
+    }
+}
+
+/// copy reader into writer, only its first limit bytes if any, followed by `TRUNCATED_MARKER`
+/// and setting exceeded if there are more, which are then read and dropped
+fn copy_limited(
+    reader: &mut impl Read,
+    writer: &mut impl Write,
+    limit: Option<u64>,
+    exceeded: &AtomicBool,
+) -> io::Result<()> {
+    let Some(limit) = limit else {
+        io::copy(reader, writer)?;
+        return Ok(());
+    };
+    io::copy(&mut reader.by_ref().take(limit), writer)?;
+    let mut buf = [0; 8192];
+    loop {
+        match reader.read(&mut buf) {
+            Ok(0) => return Ok(()),
+            Ok(_) => break,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    writer.write_all(TRUNCATED_MARKER.as_bytes())?;
+    writer.flush()?;
+    exceeded.store(true, Ordering::SeqCst);
+    io::copy(reader, &mut io::sink())?;
+    Ok(())
+}
+
+/// copy into file, after header, what is written to the returned sink until all its handles
+/// are closed, compressed if compress is set and truncated after limit bytes (see
+/// `copy_limited`), the thread must then be joined for the file to be complete
+fn pipe_sink(
+    file: fs::File,
+    header: Option<&str>,
+    compress: bool,
+    limit: Option<u64>,
+    exceeded: Arc<AtomicBool>,
+) -> io::Result<(Sink, JoinHandle<io::Result<()>>)> {
+    let (mut reader, writer) = io::pipe()?;
+    let copier = if compress {
+        let mut encoder = GzEncoder::new(file, Compression::default());
+        if let Some(header) = header {
+            encoder.write_all(header.as_bytes())?;
+        }
+        std::thread::spawn(move || {
+            copy_limited(&mut reader, &mut encoder, limit, &exceeded)?;
+            encoder.finish()?;
+            Ok(())
+        })
+    } else {
+        let mut file = file;
+        if let Some(header) = header {
+            file.write_all(header.as_bytes())?;
+        }
+        std::thread::spawn(move || copy_limited(&mut reader, &mut file, limit, &exceeded))
+    };
+    Ok((Sink::Pipe(writer), copier))
+}
+
+/// create one output file `<out_dir>/<name>.<ext>` per extension given
+/// if one of them already exists and `overwrite` is not set, `<name>.<n>.<ext>` are created instead,
+/// with `n` the first number for which none of the files exists
+fn create_output_files(
+    out_dir: &Path,
+    name: &str,
+    exts: &[&str],
+    overwrite: bool,
+) -> io::Result<Vec<(PathBuf, fs::File)>> {
+    if overwrite {
+        return exts
+            .iter()
+            .map(|ext| {
+                let path = out_dir.join(format!("{}.{}", name, ext));
+                fs::File::create(&path).map(|file| (path, file))
+            })
+            .collect();
+    }
+    let mut n = 0;
+    loop {
+        let stem = match n {
+            0 => name.to_string(),
+            n => format!("{}.{}", name, n),
+        };
+        let mut created = Vec::new();
+        let mut files = Vec::new();
+        for ext in exts {
+            let path = out_dir.join(format!("{}.{}", stem, ext));
+            match fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&path)
+            {
+                Ok(file) => {
+                    files.push((path.clone(), file));
+                    created.push(path);
+                }
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => break,
+                Err(e) => return Err(e),
+            }
+        }
+        if files.len() == exts.len() {
+            return Ok(files);
+        }
+        // this stem is (partially) taken, remove what we created and try the next one
+        for path in created {
+            fs::remove_file(path)?;
+        }
+        n += 1;
+    }
+}
+
+/// the directory of the outputs of a task, `<out_dir>/<benchset>` for tasks of a benchset
+fn task_out_dir(out_dir: &Path, task: &Task) -> PathBuf {
+    match &task.benchset {
+        Some(benchset) => out_dir.join(sanitize_filename(benchset)),
+        None => out_dir.to_path_buf(),
+    }
+}
+
+/// name turned into a file name that stays in its directory and is valid everywhere:
+/// the path separators, whitespaces, control characters and the characters Windows forbids
+/// are replaced by `_`, and so are the leading dots, so that it is neither hidden, `.` nor `..`
+pub fn sanitize_filename(name: &str) -> String {
+    let mut leading = true;
+    let res: String = name
+        .chars()
+        .map(|c| {
+            leading &= c == '.';
+            let unsafe_char = c.is_whitespace()
+                || c.is_control()
+                || matches!(c, '/' | '\\' | '<' | '>' | ':' | '"' | '|' | '?' | '*');
+            if unsafe_char || leading {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect();
+    if res.is_empty() {
+        "_".to_string()
+    } else {
+        res
+    }
+}
+
+/// the path of the program run by a task: looked up in PATH (the one of the task if it sets it)
+/// if it has no slash, relative to the working directory of the task otherwise,
+/// `None` if it cannot be found
+fn resolve_program(task: &Task) -> Option<PathBuf> {
+    if task.cmd.contains('/') {
+        let path = match &task.cwd {
+            Some(cwd) => cwd.join(&task.cmd),
+            None => PathBuf::from(&task.cmd),
+        };
+        return path.exists().then_some(path);
+    }
+    let path_var = match task.env.iter().rev().find(|(key, _)| key == "PATH") {
+        Some((_, value)) => Some(value.into()),
+        None => std::env::var_os("PATH"),
+    }?;
+    std::env::split_paths(&path_var)
+        .map(|dir| dir.join(&task.cmd))
+        .find(|path| path.is_file())
+}
+
+/// whether the file at path can be executed
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    fs::metadata(path).is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+}
+
+/// whether the file at path can be executed, any file elsewhere than on Unix
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+/// check that the program of each task exists and can be executed,
+/// return the problems found, once per program
+pub fn preflight(tasks: &[Task]) -> Result<(), Vec<String>> {
+    let mut checked = HashSet::new();
+    let mut errors = Vec::new();
+    for task in tasks {
+        if !checked.insert((&task.cmd, &task.cwd)) {
+            continue;
+        }
+        match resolve_program(task) {
+            None => errors.push(format!(
+                "program {} of task {} not found",
+                task.cmd, task.name
+            )),
+            Some(path) if !is_executable(&path) => errors.push(format!(
+                "program {:?} of task {} is not executable",
+                path, task.name
+            )),
+            Some(_) => {}
+        }
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+/// how often a running task checks whether the run is stopped
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// first and longest wait before opening files again when the process has too many open
+const FD_BACKOFF_MIN: Duration = Duration::from_millis(10);
+const FD_BACKOFF_MAX: Duration = Duration::from_secs(1);
+/// give up opening files after waiting this long in total
+const FD_WAIT_MAX: Duration = Duration::from_secs(60);
+
+/// whether e is caused by too many open files, in the process (EMFILE) or the system (ENFILE)
+fn too_many_open_files(e: &io::Error) -> bool {
+    matches!(e.raw_os_error(), Some(libc::ENFILE) | Some(libc::EMFILE))
+}
+
+/// call open until it succeeds or fails with another error than too many open files,
+/// waiting longer and longer between the calls for the other tasks to close theirs
+fn with_fd_backoff<T>(stop: &AtomicBool, mut open: impl FnMut() -> io::Result<T>) -> io::Result<T> {
+    let start = Instant::now();
+    let mut backoff = FD_BACKOFF_MIN;
+    loop {
+        match open() {
+            Err(e)
+                if too_many_open_files(&e)
+                    && start.elapsed() < FD_WAIT_MAX
+                    && !stop.load(Ordering::SeqCst) =>
+            {
+                debug!("{}, retrying in {:?}", e, backoff);
+                std::thread::sleep(backoff);
+                backoff = (backoff * 2).min(FD_BACKOFF_MAX);
+            }
+            result => return result,
+        }
+    }
+}
+
+/// how a child terminated
+enum Wait {
+    /// by itself
+    Exited,
+    /// killed after the timeout
+    TimedOut,
+    /// killed because the run is stopped
+    Interrupted,
+    /// killed because its output exceeded `RunOptions::max_output_bytes`
+    OutputExceeded,
+}
+
+/// wait for the child to terminate, it is killed after timeout or as soon as stop or
+/// exceeded (if any) is set, with `Executor::kill` since it runs task,
+/// its peak resident memory is sampled into peak_rss_kb while it runs,
+/// return why it terminated and its exit status
+fn wait_child(
+    child: &mut Child,
+    timeout: Option<Duration>,
+    stop: &AtomicBool,
+    exceeded: Option<&AtomicBool>,
+    executor: &dyn Executor,
+    task: &Task,
+    peak_rss_kb: &mut Option<u64>,
+) -> (Wait, ExitStatus) {
+    let start = Instant::now();
+    let outcome = loop {
+        // the peak only grows, so the last sample is the closest to the actual one
+        *peak_rss_kb = sys::peak_rss_kb(child.id()).or(*peak_rss_kb);
+        if stop.load(Ordering::SeqCst) {
+            break Wait::Interrupted;
+        }
+        if exceeded.is_some_and(|exceeded| exceeded.load(Ordering::SeqCst)) {
+            break Wait::OutputExceeded;
+        }
+        let slice = match timeout {
+            // the clock is read once, the timeout could pass between two reads
+            Some(timeout) => match timeout.checked_sub(start.elapsed()) {
+                Some(left) if !left.is_zero() => POLL_INTERVAL.min(left),
+                _ => break Wait::TimedOut,
+            },
+            None => POLL_INTERVAL,
+        };
+        if let Some(status) = child.wait_timeout(slice).unwrap() {
+            return (Wait::Exited, status);
+        }
+    };
+    if let Err(e) = executor.kill(task, child) {
+        warn!("Cannot kill task {}: {}", task.name, e);
+    }
+    (outcome, child.wait().unwrap())
+}
+
+/// the hook run before or after task, named `<task name>.<hook name>` so that its outputs
+/// are next to the ones of the task, the name of the task is given in `ZEXP_TASK`
+fn each_hook(hook: &Task, task: &Task) -> Task {
+    let mut hook = hook.clone();
+    hook.name = format!("{}.{}", task.name, hook.name);
+    hook.benchset = task.benchset.clone();
+    hook.env.push(("ZEXP_TASK".to_string(), task.name.clone()));
+    hook
+}
+
+/// run a task, and rerun it while it fails and retries are left,
+/// return the outcome of the last attempt
+fn run_task(
+    task: &Task,
+    opts: &RunOptions,
+    executor: &dyn Executor,
+    stop: &AtomicBool,
+) -> TaskResult {
+    let retries = task.retries.unwrap_or(opts.retries);
+    let mut attempt = 1;
+    loop {
+        let mut result = run_once(task, opts, executor, stop);
+        result.attempts = attempt;
+        let retry =
+            !result.passed() && !result.interrupted && (!result.timed_out || opts.retry_on_timeout);
+        if !retry || attempt > retries {
+            return result;
+        }
+        attempt += 1;
+        info!(
+            "Task {} failed, retrying (attempt {}/{})",
+            task.name,
+            attempt,
+            retries + 1
+        );
+    }
+}
+
+/// run one task to completion (or timeout) and return its outcome
+fn run_once(
+    task: &Task,
+    opts: &RunOptions,
+    executor: &dyn Executor,
+    stop: &AtomicBool,
+) -> TaskResult {
+    debug!("Running task: {}", &task.name);
+    let start = Instant::now();
+    // the output files are opened here, so relative paths are resolved against
+    // the directory of zexp and not the one of the task
+    let exts: &[&str] = match (opts.merge_output, opts.compress) {
+        (true, false) => &["out"],
+        (false, false) => &["stdout", "stderr"],
+        (true, true) => &["out.gz"],
+        (false, true) => &["stdout.gz", "stderr.gz"],
+    };
+    let out_dir = task_out_dir(&opts.out_dir, task);
+    if let Err(e) = fs::create_dir_all(&out_dir) {
+        let note = format!("cannot create output directory {:?}: {}", out_dir, e);
+        return TaskResult::error(task, Vec::new(), start.elapsed(), note);
+    }
+    let created = with_fd_backoff(stop, || {
+        create_output_files(
+            &out_dir,
+            &sanitize_filename(&task.name),
+            exts,
+            opts.overwrite,
+        )
+    });
+    let (outputs, files): (Vec<PathBuf>, Vec<fs::File>) = match created {
+        Ok(created) => created.into_iter().unzip(),
+        Err(e) => {
+            let note = format!("cannot create output files: {}", e);
+            return TaskResult::error(task, Vec::new(), start.elapsed(), note);
+        }
+    };
+    let header = opts.header.then(|| header(task));
+    if let Some(batch) = executor.batch() {
+        // the job appends its outputs to the files
+        for (mut file, path) in files.into_iter().zip(&outputs) {
+            if let Err(e) = file.write_all(header.as_deref().unwrap_or_default().as_bytes()) {
+                let note = format!("cannot write to {:?}: {}", path, e);
+                return TaskResult::error(task, outputs, start.elapsed(), note);
+            }
+        }
+        return run_job(task, opts, executor, batch, outputs, start, stop);
+    }
+    let mut sinks = Vec::new();
+    let mut copiers = Vec::new();
+    // set by the copiers when the output exceeds `RunOptions::max_output_bytes`
+    let exceeded = Arc::new(AtomicBool::new(false));
+    for (mut file, path) in files.into_iter().zip(&outputs) {
+        let sink = if opts.compress || opts.max_output_bytes.is_some() {
+            pipe_sink(
+                file,
+                header.as_deref(),
+                opts.compress,
+                opts.max_output_bytes,
+                exceeded.clone(),
+            )
+            .map(|(sink, copier)| {
+                copiers.push(copier);
+                sink
+            })
+        } else {
+            match &header {
+                Some(header) => file.write_all(header.as_bytes()),
+                None => Ok(()),
+            }
+            .map(|_| Sink::File(file))
+        };
+        match sink {
+            Ok(sink) => sinks.push(sink),
+            Err(e) => {
+                let note = format!("cannot write to {:?}: {}", path, e);
+                return TaskResult::error(task, outputs, start.elapsed(), note);
+            }
+        }
+    }
+    let mem_limit_mb = task.mem_limit_mb.or(opts.mem_limit_mb);
+    let mut command = executor.command(task, mem_limit_mb, &outputs);
+    if opts.merge_output {
+        let fout = sinks.remove(0);
+        match with_fd_backoff(stop, || fout.try_clone()) {
+            Ok(ferr) => command.stderr(ferr).stdout(fout),
+            Err(e) => {
+                let note = format!("cannot open output file: {}", e);
+                return TaskResult::error(task, outputs, start.elapsed(), note);
+            }
+        };
+    } else {
+        command.stderr(sinks.remove(1)).stdout(sinks.remove(0));
+    }
+    // spawning also opens file descriptors, to report the errors of exec
+    let mut child = match with_fd_backoff(stop, || command.spawn()) {
+        Ok(child) => child,
+        Err(e) => {
+            let note = format!("cannot be started: {}", e);
+            return TaskResult::error(task, outputs, start.elapsed(), note);
+        }
+    };
+    // close our handles on the output files, so only the child holds them
+    // and they are complete as soon as it terminates
+    drop(command);
+
+    // wait with the task timeout, or forever if there is none
+    let timeout = task.timeout_secs.or(opts.default_timeout);
+    let mut peak_rss_kb = None;
+    let (wait, status) = wait_child(
+        &mut child,
+        timeout.map(Duration::from_secs),
+        stop,
+        opts.kill_on_max_output.then_some(&*exceeded),
+        executor,
+        task,
+        &mut peak_rss_kb,
+    );
+    let (exit_code, timed_out, interrupted) = match wait {
+        Wait::Exited => (status.code(), false, false),
+        Wait::OutputExceeded => {
+            info!("Task {} output too large, killed", task.name);
+            (None, false, false)
+        }
+        Wait::TimedOut => {
+            info!("Task {} timed out, killed", task.name);
+            (None, true, false)
+        }
+        Wait::Interrupted => {
+            info!("Task {} interrupted, killed", task.name);
+            (None, false, true)
+        }
+    };
+    let outcome = match wait {
+        Wait::TimedOut => TaskOutcome::TimedOut,
+        _ => TaskOutcome::from_status(status),
+    };
+    match (&wait, &outcome) {
+        // a crash of the program of the task, not a kill of zexp
+        (Wait::Exited, TaskOutcome::Signaled(_)) => warn!("Task {} {}", task.name, outcome),
+        _ => debug!("Task {} {}", task.name, outcome),
+    }
+    // a task exceeding its memory limit fails to allocate, which usually makes it exit
+    // with an error or abort, so this cannot be told apart from other failures
+    let mut note = match mem_limit_mb {
+        Some(mb) if !timed_out && !interrupted && exit_code != Some(0) => {
+            Some(format!("may have exceeded its memory limit of {} MB", mb))
+        }
+        _ => None,
+    };
+    // the piped outputs are complete once the processes started by the task,
+    // which may outlive it, have closed them
+    for copier in copiers {
+        if let Err(e) = copier.join().unwrap() {
+            error!("Task {} output cannot be written: {}", task.name, e);
+            note = Some(format!("output cannot be written: {}", e));
+        }
+    }
+    if exceeded.load(Ordering::SeqCst) {
+        let bytes = opts.max_output_bytes.unwrap_or_default();
+        note = Some(if opts.kill_on_max_output {
+            format!("killed, its output exceeded {} bytes", bytes)
+        } else {
+            format!("output truncated after {} bytes", bytes)
+        });
+    }
+    if timed_out {
+        let marker = timeout_marker(timeout.unwrap_or_default());
+        for path in &outputs {
+            if let Err(e) = append_output(path, &marker) {
+                warn!("Cannot mark the output {:?} as timed out: {}", path, e);
+            }
+        }
+    }
+
+    TaskResult {
+        name: task.name.clone(),
+        command: task.command_line(),
+        exit_code,
+        timed_out,
+        duration: start.elapsed(),
+        compression: compression(&outputs),
+        outputs,
+        attempts: 1,
+        skipped: false,
+        interrupted,
+        note,
+        labels: task.labels.clone(),
+        peak_rss_kb,
+        outcome: Some(outcome),
+        job_id: None,
+    }
+}
+
+/// run task as a job of batch: submit it with the command of executor, then ask batch how
+/// the job is doing until it ends, the job is cancelled after the timeout of the task,
+/// which counts from the submission and so includes the time spent in the queue,
+/// or as soon as stop is set
+fn run_job(
+    task: &Task,
+    opts: &RunOptions,
+    executor: &dyn Executor,
+    batch: &dyn BatchSystem,
+    outputs: Vec<PathBuf>,
+    start: Instant,
+    stop: &AtomicBool,
+) -> TaskResult {
+    let mem_limit_mb = task.mem_limit_mb.or(opts.mem_limit_mb);
+    let mut command = executor.command(task, mem_limit_mb, &outputs);
+    command.stdin(Stdio::null());
+    let submitted = match with_fd_backoff(stop, || command.output()) {
+        Ok(submitted) => submitted,
+        Err(e) => {
+            let note = format!("cannot be submitted: {}", e);
+            return TaskResult::error(task, outputs, start.elapsed(), note);
+        }
+    };
+    let job_id = match batch.job_id(&String::from_utf8_lossy(&submitted.stdout)) {
+        Some(job_id) if submitted.status.success() => job_id,
+        _ => {
+            let stderr = String::from_utf8_lossy(&submitted.stderr);
+            let note = format!("cannot be submitted: {}", stderr.trim());
+            return TaskResult::error(task, outputs, start.elapsed(), note);
+        }
+    };
+    info!("Task {} submitted as job {}", task.name, job_id);
+
+    let timeout = task.timeout_secs.or(opts.default_timeout);
+    let mut polled: Option<Instant> = None;
+    let (wait, end) = loop {
+        let timed_out = timeout.is_some_and(|secs| start.elapsed() >= Duration::from_secs(secs));
+        if timed_out || stop.load(Ordering::SeqCst) {
+            if let Err(e) = batch.cancel(&job_id) {
+                warn!(
+                    "Job {} of task {} cannot be cancelled: {}",
+                    job_id, task.name, e
+                );
+            }
+            break (
+                if timed_out {
+                    Wait::TimedOut
+                } else {
+                    Wait::Interrupted
+                },
+                None,
+            );
+        }
+        if polled.is_none_or(|at| at.elapsed() >= batch.poll_interval()) {
+            polled = Some(Instant::now());
+            match batch.job_end(&job_id) {
+                Ok(Some(end)) => break (Wait::Exited, Some(end)),
+                Ok(None) => {}
+                Err(e) => {
+                    let note = format!("job {} cannot be followed: {}", job_id, e);
+                    let _ = batch.cancel(&job_id);
+                    return TaskResult {
+                        job_id: Some(job_id),
+                        ..TaskResult::error(task, outputs, start.elapsed(), note)
+                    };
+                }
+            }
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    };
+    let (outcome, note) = match (&wait, end) {
+        (Wait::TimedOut, _) => {
+            info!("Task {} timed out, job {} cancelled", task.name, job_id);
+            (Some(TaskOutcome::TimedOut), None)
+        }
+        (Wait::Exited, Some(end)) => {
+            match end.outcome {
+                TaskOutcome::Signaled(_) => warn!("Task {} {}", task.name, end.outcome),
+                _ => debug!("Task {} {}", task.name, end.outcome),
+            }
+            (Some(end.outcome), end.note)
+        }
+        _ => {
+            info!("Task {} interrupted, job {} cancelled", task.name, job_id);
+            (None, None)
+        }
+    };
+    // the time limit of the job may also be reached before the timeout of the task
+    let timed_out = outcome == Some(TaskOutcome::TimedOut);
+    if timed_out && matches!(wait, Wait::TimedOut) {
+        let marker = timeout_marker(timeout.unwrap_or_default());
+        for path in &outputs {
+            if let Err(e) = append_output(path, &marker) {
+                warn!("Cannot mark the output {:?} as timed out: {}", path, e);
+            }
+        }
+    }
+
+    TaskResult {
+        name: task.name.clone(),
+        command: task.command_line(),
+        exit_code: match outcome {
+            Some(TaskOutcome::Ok) => Some(0),
+            Some(TaskOutcome::NonZero(code)) => Some(code),
+            _ => None,
+        },
+        timed_out,
+        duration: start.elapsed(),
+        outputs,
+        attempts: 1,
+        interrupted: matches!(wait, Wait::Interrupted),
+        note,
+        labels: task.labels.clone(),
+        outcome,
+        job_id: Some(job_id),
+        ..Default::default()
+    }
+}
+
+/// the queue of tasks, which are pulled from its end: in the order of tasks, or, if some of them
+/// have a weight, by increasing weight so that the heaviest ones start first and no long task
+/// is left for the end while the other workers are idle, the tasks without a weight get the mean
+/// weight of the others
+fn queue_order(tasks: &[Task]) -> Vec<Task> {
+    let weights: Vec<f64> = tasks.iter().filter_map(|task| task.weight).collect();
+    let mut queue = tasks.to_vec();
+    if weights.is_empty() {
+        return queue;
+    }
+    let mean = weights.iter().sum::<f64>() / weights.len() as f64;
+    // stable, so the tasks of the same weight are still pulled from the end
+    queue.sort_by(|a, b| {
+        a.weight
+            .unwrap_or(mean)
+            .total_cmp(&b.weight.unwrap_or(mean))
+    });
+    queue
+}
+
+/// Run a list of tasks with the same options
+pub struct Runner {
+    tasks: Vec<Task>,
+    opts: RunOptions,
+    csv: Option<Mutex<CsvWriter>>,
+    events: Option<Mutex<EventWriter>>,
+    progress: Option<Progress>,
+    status: Option<StatusFile>,
+    /// names of the tasks done in a previous run
+    done: HashSet<String>,
+    /// set to stop the run: no new task is started and the running ones are killed
+    stop: Arc<AtomicBool>,
+    /// set when the run is stopped because a task failed, with `RunOptions::fail_fast`
+    failed_fast: AtomicBool,
+    /// number of tasks that failed, see `RunOptions::max_failures`
+    failures: AtomicU32,
+    /// the hooks are run locally, whatever the executor of the tasks
+    hooks: Hooks,
+    executor: Box<dyn Executor>,
+    /// set when the before hook failed, no task is run then
+    hook_failed: AtomicBool,
+    /// when the last task was started, see `RunOptions::stagger`
+    last_start: Mutex<Option<Instant>>,
+    /// set when `RunOptions::max_runtime` has elapsed
+    out_of_time: AtomicBool,
+}
+
+impl Runner {
+    pub fn new(tasks: Vec<Task>, opts: RunOptions) -> Self {
+        Self {
+            tasks,
+            opts,
+            csv: None,
+            events: None,
+            progress: None,
+            status: None,
+            done: HashSet::new(),
+            stop: Arc::new(AtomicBool::new(false)),
+            failed_fast: AtomicBool::new(false),
+            failures: AtomicU32::new(0),
+            hooks: Hooks::default(),
+            executor: Box::new(LocalExecutor::default()),
+            hook_failed: AtomicBool::new(false),
+            last_start: Mutex::new(None),
+            out_of_time: AtomicBool::new(false),
+        }
+    }
+
+    /// the flag stopping the run when set, e.g. from a signal handler
+    pub fn stop_flag(&self) -> Arc<AtomicBool> {
+        self.stop.clone()
+    }
+
+    /// whether the run was stopped before all the tasks were run
+    pub fn stopped(&self) -> bool {
+        self.stop.load(Ordering::SeqCst)
+    }
+
+    /// whether the run was stopped by the failure of a task, see `RunOptions::fail_fast`
+    pub fn failed_fast(&self) -> bool {
+        self.failed_fast.load(Ordering::SeqCst)
+    }
+
+    /// whether the run was stopped because `RunOptions::max_failures` tasks failed
+    pub fn max_failures_reached(&self) -> bool {
+        self.opts
+            .max_failures
+            .is_some_and(|max| self.failures.load(Ordering::SeqCst) >= max)
+    }
+
+    /// whether the run reached `RunOptions::max_runtime`, the tasks not started then are in
+    /// the results, as not run
+    pub fn out_of_time(&self) -> bool {
+        self.out_of_time.load(Ordering::SeqCst)
+    }
+
+    /// whether the before hook failed, in which case no task was run
+    pub fn hook_failed(&self) -> bool {
+        self.hook_failed.load(Ordering::SeqCst)
+    }
+
+    /// run the tasks with executor instead of locally
+    pub fn set_executor(&mut self, executor: Box<dyn Executor>) {
+        self.executor = executor;
+    }
+
+    /// run the hooks around the tasks, see `Hooks`
+    pub fn set_hooks(&mut self, hooks: Hooks) {
+        self.hooks = hooks;
+    }
+
+    /// run a task between the before_each and after_each hooks, after its warmup run if any,
+    /// the task is not run if before_each fails
+    fn run_with_hooks(&self, task: &Task) -> TaskResult {
+        if task.warmup {
+            self.warm_up(task);
+        }
+        if let Some(hook) = &self.hooks.before_each {
+            let hook_result = run_task(
+                &each_hook(hook, task),
+                &self.opts,
+                &LocalExecutor::default(),
+                &self.stop,
+            );
+            if !hook_result.passed() {
+                error!("before_each hook of task {} failed", task.name);
+                // the task is not run, so the failure that counts is the one of the hook
+                self.fail_fast(&hook_result);
+                return TaskResult::not_run(task, "not run, its before_each hook failed".into());
+            }
+        }
+        let result = run_task(task, &self.opts, self.executor.as_ref(), &self.stop);
+        if let Some(hook) = &self.hooks.after_each {
+            let hook_result = run_task(
+                &each_hook(hook, task),
+                &self.opts,
+                &LocalExecutor::default(),
+                &self.stop,
+            );
+            if !hook_result.passed() {
+                error!("after_each hook of task {} failed", task.name);
+            }
+        }
+        result
+    }
+
+    /// run task once as `<name>.warmup`, without its hooks nor retries, and drop its result
+    /// and its outputs, see `Task::warmup`
+    fn warm_up(&self, task: &Task) {
+        let warmup = Task {
+            name: format!("{}.warmup", task.name),
+            retries: Some(0),
+            warmup: false,
+            description: None,
+            weight: None,
+            ..task.clone()
+        };
+        debug!("Warming up task {}", task.name);
+        let result = run_task(&warmup, &self.opts, self.executor.as_ref(), &self.stop);
+        if !result.passed() && !result.interrupted {
+            warn!("Warmup of task {} failed, running it anyway", task.name);
+        }
+        for output in &result.outputs {
+            if let Err(e) = fs::remove_file(output) {
+                warn!("Cannot remove the warmup output {:?}: {}", output, e);
+            }
+        }
+    }
+
+    /// wait for `RunOptions::stagger` since the start of the previous task,
+    /// return false if the run was stopped meanwhile, the task must not be started then
+    fn wait_stagger(&self) -> bool {
+        let Some(stagger) = self.opts.stagger else {
+            return true;
+        };
+        // the lock is held while sleeping, so the other workers wait for it without spinning
+        // and start their task one at a time
+        let mut last_start = self.last_start.lock().unwrap();
+        if let Some(last_start) = *last_start {
+            if let Some(remaining) = stagger.checked_sub(last_start.elapsed()) {
+                std::thread::sleep(remaining);
+            }
+        }
+        *last_start = Some(Instant::now());
+        !self.stopped() && !self.out_of_time()
+    }
+
+    /// wait until `RunOptions::max_runtime` has elapsed since start, then set `out_of_time`,
+    /// and with `fail_fast` stop the run, return early when finished is set
+    fn watch_runtime(&self, start: Instant, finished: &AtomicBool) {
+        let Some(max_runtime) = self.opts.max_runtime else {
+            return;
+        };
+        while !finished.load(Ordering::SeqCst) {
+            let remaining = max_runtime.saturating_sub(start.elapsed());
+            if remaining.is_zero() {
+                warn!(
+                    "Maximum runtime of {} reached, no new task is started",
+                    HumanDuration(max_runtime)
+                );
+                self.out_of_time.store(true, Ordering::SeqCst);
+                if self.opts.fail_fast {
+                    self.stop.store(true, Ordering::SeqCst);
+                }
+                return;
+            }
+            // woken up regularly to return soon after the run is finished
+            std::thread::sleep(remaining.min(Duration::from_millis(100)));
+        }
+    }
+
+    /// log the last lines of the outputs of the task of result if it failed or timed out,
+    /// see `RunOptions::tail`
+    fn log_tail(&self, result: &TaskResult) {
+        let Some(n) = self.opts.tail else {
+            return;
+        };
+        if result.passed() || result.skipped || result.interrupted || n == 0 {
+            return;
+        }
+        for path in &result.outputs {
+            match tail_lines(path, n) {
+                Ok(lines) if lines.is_empty() => {}
+                Ok(lines) => error!(
+                    "Task {} failed, last lines of {:?}:\n{}",
+                    result.name,
+                    path,
+                    lines.join("\n")
+                ),
+                Err(e) => warn!("Cannot read the output {:?}: {}", path, e),
+            }
+        }
+    }
+
+    /// count the task of result if it failed, and stop the run if it must fail fast or too
+    /// many tasks failed
+    fn fail_fast(&self, result: &TaskResult) {
+        if result.passed() || result.skipped || result.interrupted || result.not_started() {
+            return;
+        }
+        let failures = self.failures.fetch_add(1, Ordering::SeqCst) + 1;
+        if self.opts.fail_fast {
+            // only the first failure stops the run, the tasks it kills are not failures
+            if !self.stop.swap(true, Ordering::SeqCst) {
+                warn!("Task {} failed, stopping the run", result.name);
+                self.failed_fast.store(true, Ordering::SeqCst);
+            }
+        } else if self.opts.max_failures == Some(failures)
+            && !self.stop.swap(true, Ordering::SeqCst)
+        {
+            warn!("{} tasks failed, stopping the run", failures);
+        }
+    }
+
+    /// record the results of a previous run, the tasks that passed (or were skipped) in it
+    /// are considered done when resuming
+    pub fn set_previous_results(&mut self, results: &[TaskResult]) {
+        self.done = results
+            .iter()
+            .filter(|r| r.passed() || r.skipped)
+            .map(|r| r.name.clone())
+            .collect();
+    }
+
+    /// when resuming, a task is done, and skipped, if either:
+    /// - it passed (or was skipped) in the results of the previous run
+    /// - its output file `<out_dir>/<name>.out` (or `<name>.stdout` if outputs are not merged,
+    ///   in the `<benchset>` subdirectory for tasks of a benchset) has some output after its header
+    ///
+    /// return the existing output file if any
+    fn is_done(&self, task: &Task) -> Option<Vec<PathBuf>> {
+        let ext = if self.opts.merge_output {
+            "out"
+        } else {
+            "stdout"
+        };
+        let gz = if self.opts.compress { ".gz" } else { "" };
+        let output = task_out_dir(&self.opts.out_dir, task).join(format!(
+            "{}.{}{}",
+            sanitize_filename(&task.name),
+            ext,
+            gz
+        ));
+        let output_done = has_output(&output);
+        if output_done {
+            Some(vec![output])
+        } else if self.done.contains(&task.name) {
+            Some(Vec::new())
+        } else {
+            None
+        }
+    }
+
+    /// also write the results to a CSV file while running
+    pub fn set_csv(&mut self, csv: CsvWriter) {
+        self.csv = Some(Mutex::new(csv));
+    }
+
+    /// also write the starts and the ends of the tasks while running
+    pub fn set_events(&mut self, events: EventWriter) {
+        self.events = Some(Mutex::new(events));
+    }
+
+    /// write an event with write, logging the failure to do so
+    fn event<F: FnOnce(&mut EventWriter) -> io::Result<()>>(&self, write: F) {
+        if let Some(events) = &self.events {
+            if let Err(e) = write(&mut events.lock().unwrap()) {
+                error!("Cannot write the event: {}", e);
+            }
+        }
+    }
+
+    /// also write the status of the run to a file while running, see `StatusFile`
+    pub fn set_status(&mut self, status: StatusFile) {
+        self.status = Some(status);
+    }
+
+    /// update the status file with update, logging the failure to do so
+    fn update_status<F: FnOnce(&StatusFile) -> io::Result<()>>(&self, update: F) {
+        if let Some(status) = &self.status {
+            if let Err(e) = update(status) {
+                warn!("Cannot write the status file: {}", e);
+            }
+        }
+    }
+
+    /// show the progress of the run on a progress bar
+    pub fn set_progress(&mut self, progress: Progress) {
+        self.progress = Some(progress);
+    }
+
+    /// check that the program of each task exists and can be executed, see `preflight`
+    pub fn preflight(&self) -> Result<(), Vec<String>> {
+        preflight(&self.tasks)
+    }
+
+    /// run all the tasks on `num_cores` workers, each worker pulls the next task from a shared
+    /// queue until it is empty, so every task is run exactly once,
+    /// or until the run is stopped (see `stop_flag`), the tasks not started are left out of the results,
+    /// or until it is out of time (see `out_of_time`), they are then in the results as not run
+    /// the before hook is run first, no task is run if it fails (see `hook_failed`),
+    /// the after hook is run last, even if the run is stopped
+    pub fn run(&self, num_cores: usize) -> Vec<TaskResult> {
+        if let Some(hook) = &self.hooks.before {
+            if !run_task(hook, &self.opts, &LocalExecutor::default(), &self.stop).passed() {
+                error!("before hook failed, no task is run");
+                self.hook_failed.store(true, Ordering::SeqCst);
+                return Vec::new();
+            }
+        }
+        let results = self.run_tasks(num_cores);
+        if let Some(hook) = &self.hooks.after {
+            // not killed by the stop of the run, the teardown is still needed then
+            if !run_task(
+                hook,
+                &self.opts,
+                &LocalExecutor::default(),
+                &AtomicBool::new(false),
+            )
+            .passed()
+            {
+                error!("after hook failed");
+            }
+        }
+        results
+    }
+
+    fn run_tasks(&self, num_cores: usize) -> Vec<TaskResult> {
+        if let Some(progress) = &self.progress {
+            progress.start(self.tasks.len());
+        }
+        self.update_status(|status| status.start(self.tasks.len()));
+        let queue = Mutex::new(queue_order(&self.tasks));
+        let results = Mutex::new(Vec::with_capacity(self.tasks.len()));
+        // with no worker no task would be run, and rayon would use its default number of threads
+        let num_cores = num_cores.max(1);
+        let pool = match num_cores {
+            // a single worker runs on the current thread, no pool is needed
+            1 => None,
+            _ => match rayon::ThreadPoolBuilder::new()
+                .num_threads(num_cores)
+                .build()
+            {
+                Ok(pool) => Some(pool),
+                Err(e) => {
+                    error!(
+                        "Cannot create the {} workers: {}, running the tasks one at a time",
+                        num_cores, e
+                    );
+                    None
+                }
+            },
+        };
+        let start = Instant::now();
+        let finished = AtomicBool::new(false);
+        std::thread::scope(|scope| {
+            scope.spawn(|| self.watch_runtime(start, &finished));
+            match pool {
+                Some(pool) => pool.scope(|s| {
+                    for _ in 0..num_cores {
+                        s.spawn(|_| self.work(&queue, &results));
+                    }
+                }),
+                None => self.work(&queue, &results),
+            }
+            finished.store(true, Ordering::SeqCst);
+        });
+        if let Some(progress) = &self.progress {
+            progress.finish();
+        }
+
+        let mut results = results.into_inner().unwrap();
+        if self.out_of_time() {
+            // in the order they would have been run
+            for task in queue.into_inner().unwrap().iter().rev() {
+                let note = "not run, the maximum runtime was reached".to_string();
+                results.push(TaskResult::not_run(task, note));
+            }
+        }
+        results
+    }
+
+    /// run the tasks of queue until it is empty or the run is stopped, adding their results
+    /// to results, the loop of each worker
+    fn work(&self, queue: &Mutex<Vec<Task>>, results: &Mutex<Vec<TaskResult>>) {
+        loop {
+            if self.stopped() || self.out_of_time() {
+                break;
+            }
+            // the lock is released at the end of this statement, not held while running
+            let task = match queue.lock().unwrap().pop() {
+                Some(task) => task,
+                None => break,
+            };
+            let done = if self.opts.resume {
+                self.is_done(&task)
+            } else {
+                None
+            };
+            let result = match done {
+                Some(outputs) => {
+                    debug!("Task {} already done, skipped", task.name);
+                    TaskResult::skipped(&task, outputs)
+                }
+                None => {
+                    if !self.wait_stagger() {
+                        // back in the queue, to be reported as not run if out of time
+                        queue.lock().unwrap().push(task);
+                        break;
+                    }
+                    self.event(|events| events.start(&task));
+                    self.update_status(|status| status.task_started(&task.name));
+                    self.run_with_hooks(&task)
+                }
+            };
+            self.log_tail(&result);
+            self.fail_fast(&result);
+            self.event(|events| events.finish(&result));
+            if let Some(csv) = &self.csv {
+                if let Err(e) = csv.lock().unwrap().write(&result) {
+                    error!(
+                        "Cannot write the result of {} to the CSV: {}",
+                        result.name, e
+                    );
+                }
+            }
+            if let Some(progress) = &self.progress {
+                progress.task_done(&result);
+            }
+            self.update_status(|status| status.task_done(&result));
+            results.lock().unwrap().push(result);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::executor::JobEnd;
+    use flate2::read::GzDecoder;
+
+    /// a task running `true`
+    fn true_task(name: &str) -> Task {
+        Task::builder().name(name).cmd("true").build().unwrap()
+    }
+
+    /// default options, with merged outputs without header in a fresh temporary directory
+    fn test_opts(dir_name: &str) -> RunOptions {
+        let out_dir = std::env::temp_dir().join(dir_name);
+        let _ = fs::remove_dir_all(&out_dir);
+        fs::create_dir_all(&out_dir).unwrap();
+        RunOptions {
+            out_dir,
+            merge_output: true,
+            header: false,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_header() {
+        let mut opts = test_opts("zexp-test-header");
+        opts.header = true;
+        let out_dir = opts.out_dir.clone();
+        let echo = Task {
+            cmd: "echo".to_string(),
+            args: vec!["hello world".to_string()],
+            ..true_task("echo")
+        };
+        let results = Runner::new(vec![echo, true_task("true")], opts).run(1);
+        assert!(results.iter().all(|r| r.passed()));
+        let output = fs::read_to_string(out_dir.join("echo.out")).unwrap();
+        let (header, rest) = output.split_once('\n').unwrap();
+        assert!(header.starts_with("# zexp: echo 'hello world' (at "));
+        assert_eq!(rest, "hello world\n");
+        assert!(has_output(&out_dir.join("echo.out")));
+        // only the header, the task printed nothing
+        assert!(!has_output(&out_dir.join("true.out")));
+        assert!(!has_output(&out_dir.join("missing.out")));
+    }
+
+    #[test]
+    fn test_compress() {
+        let mut opts = test_opts("zexp-test-compress");
+        opts.header = true;
+        opts.compress = true;
+        let out_dir = opts.out_dir.clone();
+        let echo = Task {
+            cmd: "sh".to_string(),
+            args: vec!["-c".to_string(), "echo out; echo err >&2".to_string()],
+            ..true_task("echo")
+        };
+        let results = Runner::new(vec![echo, true_task("true")], opts).run(1);
+        assert!(results.iter().all(|r| r.passed()));
+        assert!(results
+            .iter()
+            .all(|r| r.compression.as_deref() == Some("gzip")));
+        let path = out_dir.join("echo.out.gz");
+        let echo = results.iter().find(|r| r.name == "echo").unwrap();
+        assert_eq!(echo.outputs, vec![path.clone()]);
+        let mut output = String::new();
+        GzDecoder::new(fs::File::open(&path).unwrap())
+            .read_to_string(&mut output)
+            .unwrap();
+        let (header, rest) = output.split_once('\n').unwrap();
+        assert!(header.starts_with("# zexp: sh -c "));
+        assert_eq!(rest, "out\nerr\n");
+        assert!(has_output(&path));
+        assert!(!has_output(&out_dir.join("true.out.gz")));
+    }
+
+    #[test]
+    fn test_outcome() {
+        let opts = test_opts("zexp-test-outcome");
+        let run = |cmd: &str, args: &[&str]| {
+            let task = Task {
+                cmd: cmd.to_string(),
+                args: args.iter().map(|arg| arg.to_string()).collect(),
+                ..true_task(cmd.rsplit('/').next().unwrap())
+            };
+            run_once(
+                &task,
+                &opts,
+                &LocalExecutor::default(),
+                &AtomicBool::new(false),
+            )
+            .outcome
+            .unwrap()
+        };
+        assert_eq!(run("true", &[]), TaskOutcome::Ok);
+        assert_eq!(run("sh", &["-c", "exit 3"]), TaskOutcome::NonZero(3));
+        let outcome = run("/nonexistent/tool", &[]);
+        assert!(
+            matches!(outcome, TaskOutcome::SpawnFailed(ref e) if e.contains("cannot be started"))
+        );
+        #[cfg(unix)]
+        {
+            let outcome = run("sh", &["-c", "kill -SEGV $$"]);
+            assert_eq!(outcome, TaskOutcome::Signaled(libc::SIGSEGV));
+            assert!(outcome.to_string().ends_with("(SIGSEGV)"), "{}", outcome);
+            assert_eq!(
+                serde_json::to_string(&outcome).unwrap(),
+                format!("{{\"signaled\":{}}}", libc::SIGSEGV)
+            );
+        }
+        assert_eq!(serde_json::to_string(&TaskOutcome::Ok).unwrap(), "\"ok\"");
+    }
+
+    /// a batch system whose jobs run when submitted and end after some polls
+    struct FakeBatch {
+        polls: AtomicU32,
+        end_after: u32,
+        cancelled: Mutex<Vec<String>>,
+    }
+
+    impl Executor for FakeBatch {
+        fn command(
+            &self,
+            _task: &Task,
+            _mem_limit_mb: Option<u64>,
+            outputs: &[PathBuf],
+        ) -> std::process::Command {
+            let mut command = std::process::Command::new("sh");
+            command
+                .args(["-c", "echo ran >> \"$0\"; echo 42"])
+                .arg(&outputs[0]);
+            command
+        }
+
+        fn batch(&self) -> Option<&dyn BatchSystem> {
+            Some(self)
+        }
+    }
+
+    impl BatchSystem for FakeBatch {
+        fn job_id(&self, submission: &str) -> Option<String> {
+            Some(submission.trim().to_string())
+        }
+
+        fn job_end(&self, _job_id: &str) -> io::Result<Option<JobEnd>> {
+            let polls = self.polls.fetch_add(1, Ordering::SeqCst) + 1;
+            Ok((polls >= self.end_after).then_some(JobEnd {
+                outcome: TaskOutcome::NonZero(2),
+                note: None,
+            }))
+        }
+
+        fn cancel(&self, job_id: &str) -> io::Result<()> {
+            self.cancelled.lock().unwrap().push(job_id.to_string());
+            Ok(())
+        }
+
+        fn poll_interval(&self) -> Duration {
+            Duration::ZERO
+        }
+    }
+
+    #[test]
+    fn test_run_job() {
+        let opts = test_opts("zexp-test-run-job");
+        let out_dir = opts.out_dir.clone();
+        let batch = FakeBatch {
+            polls: AtomicU32::new(0),
+            end_after: 3,
+            cancelled: Mutex::new(Vec::new()),
+        };
+        let result = run_once(&true_task("job"), &opts, &batch, &AtomicBool::new(false));
+        assert_eq!(result.job_id.as_deref(), Some("42"));
+        assert_eq!(result.outcome, Some(TaskOutcome::NonZero(2)));
+        assert_eq!(result.exit_code, Some(2));
+        assert_eq!(batch.polls.load(Ordering::SeqCst), 3);
+        assert!(batch.cancelled.lock().unwrap().is_empty());
+        assert_eq!(
+            fs::read_to_string(out_dir.join("job.out")).unwrap(),
+            "ran\n"
+        );
+
+        // cancelled after its timeout
+        let opts = RunOptions {
+            default_timeout: Some(1),
+            overwrite: true,
+            ..opts
+        };
+        let batch = FakeBatch {
+            end_after: u32::MAX,
+            ..batch
+        };
+        let result = run_once(&true_task("job"), &opts, &batch, &AtomicBool::new(false));
+        assert!(result.timed_out);
+        assert_eq!(result.exit_code, None);
+        assert_eq!(*batch.cancelled.lock().unwrap(), ["42"]);
+        assert!(fs::read_to_string(out_dir.join("job.out"))
+            .unwrap()
+            .ends_with("killed ===\n"));
+    }
+
+    #[test]
+    fn test_timeout_marker() {
+        let opts = RunOptions {
+            default_timeout: Some(1),
+            merge_output: false,
+            ..test_opts("zexp-test-timeout-marker")
+        };
+        let out_dir = opts.out_dir.clone();
+        let task = Task {
+            cmd: "sh".to_string(),
+            args: vec![
+                "-c".to_string(),
+                "printf started; exec sleep 10".to_string(),
+            ],
+            ..true_task("slow")
+        };
+        let result = run_once(
+            &task,
+            &opts,
+            &LocalExecutor::default(),
+            &AtomicBool::new(false),
+        );
+        assert!(result.timed_out);
+        assert_eq!(result.outcome, Some(TaskOutcome::TimedOut));
+        let marker = "started\n=== ZEXP: task timed out after 1s, killed ===\n";
+        assert_eq!(
+            fs::read_to_string(out_dir.join("slow.stdout")).unwrap(),
+            marker
+        );
+        assert!(fs::read_to_string(out_dir.join("slow.stderr"))
+            .unwrap()
+            .ends_with("killed ===\n"));
+
+        // in a gzip member of its own
+        let opts = RunOptions {
+            compress: true,
+            ..opts
+        };
+        let result = run_once(
+            &task,
+            &opts,
+            &LocalExecutor::default(),
+            &AtomicBool::new(false),
+        );
+        assert!(result.timed_out);
+        let path = out_dir.join("slow.stdout.gz");
+        assert_eq!(
+            tail_lines(&path, 2).unwrap(),
+            ["started", marker.lines().nth(1).unwrap()]
+        );
+    }
+
+    #[test]
+    fn test_copy_limited() {
+        let copy = |input: &str, limit| {
+            let exceeded = AtomicBool::new(false);
+            let mut output = Vec::new();
+            copy_limited(&mut input.as_bytes(), &mut output, limit, &exceeded).unwrap();
+            (String::from_utf8(output).unwrap(), exceeded.into_inner())
+        };
+        assert_eq!(copy("hello", None), ("hello".to_string(), false));
+        assert_eq!(copy("hello", Some(5)), ("hello".to_string(), false));
+        let truncated = format!("hel{}", TRUNCATED_MARKER);
+        assert_eq!(copy("hello", Some(3)), (truncated, true));
+    }
+
+    #[test]
+    fn test_max_output_bytes() {
+        let mut opts = test_opts("zexp-test-max-output-bytes");
+        opts.max_output_bytes = Some(100);
+        let out_dir = opts.out_dir.clone();
+        let yes = |name: &str, script: &str| Task {
+            cmd: "sh".to_string(),
+            args: vec!["-c".to_string(), script.to_string()],
+            ..true_task(name)
+        };
+        let tasks = vec![yes("head", "yes | head -c 10000"), yes("small", "echo ok")];
+        let results = Runner::new(tasks, opts).run(1);
+        assert!(results.iter().all(|r| r.passed()));
+        let output = fs::read_to_string(out_dir.join("head.out")).unwrap();
+        assert_eq!(output, format!("{}{}", "y\n".repeat(50), TRUNCATED_MARKER));
+        let head = results.iter().find(|r| r.name == "head").unwrap();
+        assert!(head
+            .note
+            .as_ref()
+            .unwrap()
+            .contains("truncated after 100 bytes"));
+        let output = fs::read_to_string(out_dir.join("small.out")).unwrap();
+        assert_eq!(output, "ok\n");
+
+        // a task that never stops writing is killed
+        let mut opts = test_opts("zexp-test-kill-on-max-output");
+        opts.max_output_bytes = Some(100);
+        opts.kill_on_max_output = true;
+        let results = Runner::new(vec![yes("yes", "exec yes")], opts).run(1);
+        assert!(!results[0].passed());
+        assert!(results[0].note.as_ref().unwrap().contains("killed"));
+    }
+
+    #[test]
+    fn test_queue_order() {
+        let task = |name: &str, weight: Option<f64>| Task {
+            weight,
+            ..true_task(name)
+        };
+        let names = |queue: Vec<Task>| -> Vec<String> {
+            // in the order they are pulled
+            queue.into_iter().rev().map(|task| task.name).collect()
+        };
+        let unweighted = [task("a", None), task("b", None)];
+        assert_eq!(names(queue_order(&unweighted)), ["b", "a"]);
+        let weighted = [
+            task("short", Some(1.0)),
+            task("long", Some(100.0)),
+            task("unknown", None),
+            task("mid", Some(10.0)),
+            task("mid2", Some(10.0)),
+        ];
+        // the unknown one gets the mean weight, 27.75
+        assert_eq!(
+            names(queue_order(&weighted)),
+            ["long", "unknown", "mid2", "mid", "short"]
+        );
+    }
+
+    #[test]
+    fn test_sanitize_filename() {
+        assert_eq!(sanitize_filename("fft"), "fft");
+        assert_eq!(sanitize_filename("x=1,y=2#3"), "x=1,y=2#3");
+        assert_eq!(sanitize_filename("kernel/fft"), "kernel_fft");
+        assert_eq!(sanitize_filename("../etc/passwd"), "___etc_passwd");
+        assert_eq!(sanitize_filename("fft -O2\t"), "fft_-O2_");
+        assert_eq!(sanitize_filename(".hidden.v2"), "_hidden.v2");
+        assert_eq!(sanitize_filename(".."), "__");
+        assert_eq!(sanitize_filename("a\\b:c*"), "a_b_c_");
+        assert_eq!(sanitize_filename(""), "_");
+
+        // the output file is named after the sanitized name, the result keeps the original one
+        let opts = test_opts("zexp-test-sanitize-filename");
+        let out_dir = opts.out_dir.clone();
+        let results = Runner::new(vec![true_task("grid/x=1 y=2")], opts).run(1);
+        assert_eq!(results[0].name, "grid/x=1 y=2");
+        assert_eq!(results[0].outputs, [out_dir.join("grid_x=1_y=2.out")]);
+        assert!(out_dir.join("grid_x=1_y=2.out").exists());
+    }
+
+    #[test]
+    fn test_output_file_suffix() {
+        let out_dir = std::env::temp_dir().join("zexp-test-output-file-suffix");
+        let _ = fs::remove_dir_all(&out_dir);
+        fs::create_dir_all(&out_dir).unwrap();
+        let exts = ["stdout", "stderr"];
+        create_output_files(&out_dir, "fft", &exts, false).unwrap();
+        // only one of the files is taken, the suffix must still be shared by both
+        fs::remove_file(out_dir.join("fft.stderr")).unwrap();
+        create_output_files(&out_dir, "fft", &exts, false).unwrap();
+        create_output_files(&out_dir, "fft", &exts, true).unwrap();
+        assert!(out_dir.join("fft.stdout").exists());
+        assert!(out_dir.join("fft.stderr").exists());
+        assert!(out_dir.join("fft.1.stdout").exists());
+        assert!(out_dir.join("fft.1.stderr").exists());
+        assert!(!out_dir.join("fft.2.stdout").exists());
+    }
+
+    #[test]
+    fn test_events() {
+        let opts = test_opts("zexp-test-events");
+        let path = opts.out_dir.join("events.jsonl");
+        let fail = Task {
+            cmd: "false".to_string(),
+            ..true_task("ko")
+        };
+        let mut runner = Runner::new(vec![true_task("ok"), fail], opts);
+        runner.set_events(EventWriter::create(&path).unwrap());
+        runner.run(1);
+        let events: Vec<serde_json::Value> = fs::read_to_string(&path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(events.len(), 4);
+        // the tasks are pulled from the end of the queue
+        assert_eq!(events[0]["event"], "start");
+        assert_eq!(events[0]["name"], "ko");
+        assert_eq!(events[1]["event"], "finish");
+        assert_eq!(events[1]["code"], 1);
+        assert_eq!(events[1]["passed"], false);
+        assert_eq!(events[3]["name"], "ok");
+        assert_eq!(events[3]["passed"], true);
+        assert!(events[3]["duration"].is_f64());
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_peak_rss() {
+        let opts = test_opts("zexp-test-peak-rss");
+        let task = Task {
+            cmd: "sleep".to_string(),
+            args: vec!["0.3".to_string()],
+            ..true_task("sleep")
+        };
+        let result = run_once(
+            &task,
+            &opts,
+            &LocalExecutor::default(),
+            &AtomicBool::new(false),
+        );
+        assert!(result.peak_rss_kb.is_some_and(|kb| kb > 0));
+        let json = serde_json::to_value(&result).unwrap();
+        assert!(json["peak_rss_kb"].is_u64());
+    }
+
+    #[test]
+    fn test_tail_lines() {
+        let dir = std::env::temp_dir().join("zexp-test-tail-lines");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("fft.out");
+        fs::write(&path, format!("{}fft\na\nb\r\nc\n", HEADER_PREFIX)).unwrap();
+        assert_eq!(tail_lines(&path, 2).unwrap(), ["b", "c"]);
+        assert_eq!(tail_lines(&path, 10).unwrap(), ["a", "b", "c"]);
+        let gz = dir.join("fft.out.gz");
+        let mut encoder = GzEncoder::new(fs::File::create(&gz).unwrap(), Compression::default());
+        encoder.write_all(b"a\nb").unwrap();
+        encoder.finish().unwrap();
+        assert_eq!(tail_lines(&gz, 1).unwrap(), ["b"]);
+        assert!(tail_lines(&dir.join("missing.out"), 1).is_err());
+    }
+
+    #[test]
+    fn test_csv_quote() {
+        assert_eq!(csv_quote("fft"), "fft");
+        assert_eq!(csv_quote("a,b"), "\"a,b\"");
+        assert_eq!(csv_quote("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+
+    #[test]
+    fn test_retries() {
+        let opts = RunOptions {
+            retries: 2,
+            ..test_opts("zexp-test-retries")
+        };
+        let out_dir = opts.out_dir.clone();
+        let mut fail = true_task("fail");
+        fail.cmd = "false".to_string();
+
+        let result = run_task(
+            &fail,
+            &opts,
+            &LocalExecutor::default(),
+            &AtomicBool::new(false),
+        );
+        assert_eq!(result.attempts, 3);
+        assert!(!result.passed());
+        // each attempt has its own output file
+        assert!(out_dir.join("fail.2.out").exists());
+        let result = run_task(
+            &true_task("pass"),
+            &opts,
+            &LocalExecutor::default(),
+            &AtomicBool::new(false),
+        );
+        assert_eq!(result.attempts, 1);
+    }
+
+    #[test]
+    fn test_benchset_out_dir() {
+        let opts = test_opts("zexp-test-benchset-out-dir");
+        let out_dir = opts.out_dir.clone();
+        let mut fft = true_task("fft");
+        fft.benchset = Some("kernel".to_string());
+        let results = Runner::new(vec![fft, true_task("other")], opts).run(1);
+        assert!(out_dir.join("kernel").join("fft.out").exists());
+        assert!(out_dir.join("other.out").exists());
+        assert!(results.iter().all(|r| r.passed()));
+    }
+
+    #[test]
+    fn test_preflight() {
+        let opts = test_opts("zexp-test-preflight");
+        let script = opts.out_dir.join("script.sh");
+        fs::write(&script, "#!/bin/sh\n").unwrap();
+        let mut not_executable = true_task("not_executable");
+        not_executable.cmd = script.to_str().unwrap().to_string();
+        let mut missing = true_task("missing");
+        missing.cmd = "zexp-no-such-program".to_string();
+        let mut missing_again = missing.clone();
+        missing_again.name = "missing_again".to_string();
+
+        let runner = Runner::new(vec![true_task("ok")], test_opts("zexp-test-preflight-ok"));
+        assert!(runner.preflight().is_ok());
+        let tasks = vec![true_task("ok"), not_executable, missing, missing_again];
+        let errors = Runner::new(tasks, opts).preflight().unwrap_err();
+        assert_eq!(errors.len(), 2, "{:?}", errors);
+        assert!(errors[0].contains("not executable"));
+        assert!(errors[1].contains("zexp-no-such-program"));
+    }
+
+    #[test]
+    fn test_stop() {
+        let sleep = |name: &str| Task {
+            cmd: "sleep".to_string(),
+            args: vec!["10".to_string()],
+            ..true_task(name)
+        };
+        let runner = Runner::new(
+            vec![sleep("a"), sleep("b"), sleep("c")],
+            test_opts("zexp-test-stop"),
+        );
+        let stop = runner.stop_flag();
+        let start = Instant::now();
+        let results = std::thread::scope(|s| {
+            let run = s.spawn(|| runner.run(2));
+            std::thread::sleep(Duration::from_millis(300));
+            stop.store(true, Ordering::SeqCst);
+            run.join().unwrap()
+        });
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert!(runner.stopped());
+        assert_eq!(results.len(), 2);
+        assert!(results.iter().all(|r| r.interrupted && !r.passed()));
+    }
+
+    #[test]
+    fn test_fd_backoff() {
+        let stop = AtomicBool::new(false);
+        let mut calls = 0;
+        let opened = with_fd_backoff(&stop, || {
+            calls += 1;
+            match calls {
+                1 | 2 => Err(io::Error::from_raw_os_error(24)),
+                _ => Ok(calls),
+            }
+        });
+        assert_eq!(opened.unwrap(), 3);
+
+        // other errors are returned at once
+        let mut calls = 0;
+        let opened: io::Result<()> = with_fd_backoff(&stop, || {
+            calls += 1;
+            Err(io::Error::from(io::ErrorKind::PermissionDenied))
+        });
+        assert!(opened.is_err());
+        assert_eq!(calls, 1);
+
+        // and no longer waited for once the run is stopped
+        stop.store(true, Ordering::SeqCst);
+        let opened: io::Result<()> =
+            with_fd_backoff(&stop, || Err(io::Error::from_raw_os_error(23)));
+        assert!(too_many_open_files(&opened.unwrap_err()));
+    }
+
+    #[test]
+    fn test_run_error() {
+        let mut opts = test_opts("zexp-test-run-error");
+        // a file in place of the output directory
+        fs::write(opts.out_dir.join("file"), "").unwrap();
+        opts.out_dir = opts.out_dir.join("file");
+        let results = Runner::new(vec![true_task("a")], opts).run(1);
+        assert!(!results[0].passed());
+        assert!(results[0]
+            .note
+            .as_ref()
+            .unwrap()
+            .contains("output directory"));
+
+        let task = Task {
+            cmd: "/nonexistent/zexp".to_string(),
+            ..true_task("missing")
+        };
+        let results = Runner::new(vec![task], test_opts("zexp-test-run-missing")).run(1);
+        assert!(!results[0].passed());
+        assert!(results[0]
+            .note
+            .as_ref()
+            .unwrap()
+            .contains("cannot be started"));
+    }
+
+    #[test]
+    fn test_stagger() {
+        let mut opts = test_opts("zexp-test-stagger");
+        opts.stagger = Some(Duration::from_millis(200));
+        let tasks = (0..3).map(|i| true_task(&format!("t{}", i))).collect();
+        let start = Instant::now();
+        let results = Runner::new(tasks, opts).run(3);
+        assert_eq!(results.len(), 3);
+        // the tasks are started 200ms apart even though there is a worker for each
+        assert!(start.elapsed() >= Duration::from_millis(400));
+    }
+
+    #[test]
+    fn test_max_runtime() {
+        let sleep = |name: String| Task {
+            cmd: "sleep".to_string(),
+            args: vec!["0.4".to_string()],
+            ..true_task(&name)
+        };
+        let run = |fail_fast| {
+            let mut opts = test_opts("zexp-test-max-runtime");
+            opts.max_runtime = Some(Duration::from_millis(600));
+            opts.fail_fast = fail_fast;
+            let tasks = (0..4).map(|i| sleep(format!("t{}", i))).collect();
+            let runner = Runner::new(tasks, opts);
+            let results = runner.run(1);
+            assert!(runner.out_of_time());
+            assert_eq!(results.len(), 4);
+            let not_run = results.iter().filter(|r| r.not_started()).count();
+            assert_eq!(not_run, 2);
+            // not failures of their tasks
+            assert_eq!(runner.failures.load(Ordering::SeqCst), 0);
+            assert!(results[3]
+                .note
+                .as_ref()
+                .unwrap()
+                .contains("maximum runtime"));
+            results
+        };
+        // the second task is started before the deadline and finishes after it
+        let results = run(false);
+        assert_eq!(results.iter().filter(|r| r.passed()).count(), 2);
+        // or is killed with fail_fast
+        let results = run(true);
+        assert!(results[0].passed());
+        assert!(results[1].interrupted);
+    }
+
+    #[test]
+    fn test_warmup() {
+        let opts = test_opts("zexp-test-warmup");
+        let out_dir = opts.out_dir.clone();
+        let count = out_dir.join("count");
+        // counts its runs
+        let task = Task::builder()
+            .name("count")
+            .cmd("sh")
+            .arg("-c")
+            .arg(format!("echo run >> {}", count.display()))
+            .warmup()
+            .build()
+            .unwrap();
+        let results = Runner::new(vec![task], opts).run(1);
+        assert_eq!(results.len(), 1);
+        assert!(results[0].passed());
+        assert_eq!(fs::read_to_string(&count).unwrap(), "run\nrun\n");
+        assert!(out_dir.join("count.out").exists());
+        assert!(!out_dir.join("count.warmup.out").exists());
+    }
+
+    #[test]
+    fn test_hooks() {
+        let opts = test_opts("zexp-test-hooks");
+        let out_dir = opts.out_dir.clone();
+        let fail = |name: &str| Task {
+            cmd: "false".to_string(),
+            ..true_task(name)
+        };
+        let echo_task = Task {
+            cmd: "sh".to_string(),
+            args: vec!["-c".to_string(), "echo $ZEXP_TASK".to_string()],
+            ..true_task("after_each")
+        };
+        let mut runner = Runner::new(vec![true_task("a"), true_task("b")], opts);
+        runner.set_hooks(Hooks {
+            before: Some(true_task("before")),
+            after: Some(true_task("after")),
+            before_each: None,
+            after_each: Some(echo_task),
+        });
+        let results = runner.run(1);
+        assert!(!runner.hook_failed());
+        assert_eq!(results.len(), 2);
+        assert!(results.iter().all(|r| r.passed()));
+        assert!(out_dir.join("before.out").exists());
+        assert!(out_dir.join("after.out").exists());
+        let after_a = fs::read_to_string(out_dir.join("a.after_each.out")).unwrap();
+        assert_eq!(after_a.trim(), "a");
+
+        let mut runner = Runner::new(vec![true_task("a")], test_opts("zexp-test-hooks-each"));
+        runner.set_hooks(Hooks {
+            before_each: Some(fail("before_each")),
+            ..Hooks::default()
+        });
+        let results = runner.run(1);
+        assert!(!results[0].passed());
+        assert!(results[0].not_started());
+        assert!(results[0].note.as_ref().unwrap().contains("before_each"));
+        // the failure of the hook counts
+        assert_eq!(runner.failures.load(Ordering::SeqCst), 1);
+
+        let mut runner = Runner::new(vec![true_task("a")], test_opts("zexp-test-hooks-before"));
+        runner.set_hooks(Hooks {
+            before: Some(fail("before")),
+            ..Hooks::default()
+        });
+        assert!(runner.run(1).is_empty());
+        assert!(runner.hook_failed());
+    }
+
+    #[test]
+    fn test_fail_fast() {
+        let opts = RunOptions {
+            fail_fast: true,
+            ..test_opts("zexp-test-fail-fast")
+        };
+        let sleep = |name: &str| Task {
+            cmd: "sleep".to_string(),
+            args: vec!["10".to_string()],
+            ..true_task(name)
+        };
+        let fail = Task {
+            cmd: "false".to_string(),
+            ..true_task("fail")
+        };
+        // the tasks are pulled from the end of the queue
+        let runner = Runner::new(vec![sleep("a"), sleep("b"), fail], opts);
+        let start = Instant::now();
+        let results = runner.run(2);
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert!(runner.stopped());
+        assert!(runner.failed_fast());
+        assert_eq!(results.len(), 2);
+        assert!(results.iter().any(|r| r.name == "b" && r.interrupted));
+
+        let runner = Runner::new(
+            vec![true_task("pass")],
+            test_opts("zexp-test-fail-fast-pass"),
+        );
+        runner.run(1);
+        assert!(!runner.failed_fast());
+    }
+
+    #[test]
+    fn test_max_failures() {
+        let opts = |max_failures| RunOptions {
+            max_failures: Some(max_failures),
+            ..test_opts("zexp-test-max-failures")
+        };
+        let fail = |name: &str| Task {
+            cmd: "false".to_string(),
+            ..true_task(name)
+        };
+        // the tasks are pulled from the end of the queue
+        let tasks = vec![fail("d"), fail("c"), true_task("b"), fail("a")];
+        let runner = Runner::new(tasks.clone(), opts(2));
+        let results = runner.run(1);
+        assert!(runner.stopped());
+        assert!(runner.max_failures_reached());
+        assert!(!runner.failed_fast());
+        let names: Vec<_> = results.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(names, ["a", "b", "c"]);
+
+        let runner = Runner::new(tasks, opts(4));
+        assert_eq!(runner.run(1).len(), 4);
+        assert!(!runner.stopped());
+        assert!(!runner.max_failures_reached());
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_mem_limit() {
+        let opts = RunOptions {
+            mem_limit_mb: Some(50),
+            ..test_opts("zexp-test-mem-limit")
+        };
+        let out_dir = opts.out_dir.clone();
+        let ulimit = Task {
+            cmd: "sh".to_string(),
+            args: vec!["-c".to_string(), "ulimit -v".to_string()],
+            ..true_task("ulimit")
+        };
+        let fail = Task {
+            cmd: "false".to_string(),
+            mem_limit_mb: Some(100),
+            ..true_task("fail")
+        };
+        let results = Runner::new(vec![ulimit, fail], opts).run(1);
+        // in KB
+        let limit = fs::read_to_string(out_dir.join("ulimit.out")).unwrap();
+        assert_eq!(limit.trim(), "51200");
+        let fail = results.iter().find(|r| r.name == "fail").unwrap();
+        assert!(fail.note.as_ref().unwrap().contains("100 MB"));
+        let ulimit = results.iter().find(|r| r.name == "ulimit").unwrap();
+        assert!(ulimit.note.is_none());
+    }
+
+    #[test]
+    fn test_resume() {
+        let opts = RunOptions {
+            resume: true,
+            ..test_opts("zexp-test-resume")
+        };
+        // done from its output
+        let mut echo = true_task("echo");
+        echo.cmd = "echo".to_string();
+        echo.args = vec!["hello".to_string()];
+        fs::write(opts.out_dir.join("echo.out"), "hello").unwrap();
+        // done from the previous results, even if it would fail now
+        let mut fail = true_task("fail");
+        fail.cmd = "false".to_string();
+        let mut previous = TaskResult::skipped(&fail, Vec::new());
+        previous.skipped = false;
+        previous.exit_code = Some(0);
+        // not done, its output is empty
+        fs::write(opts.out_dir.join("pass.out"), "").unwrap();
+
+        let mut runner = Runner::new(vec![echo, fail, true_task("pass")], opts);
+        runner.set_previous_results(&[previous]);
+        let results = runner.run(1);
+        let skipped = |name: &str| results.iter().find(|r| r.name == name).unwrap().skipped;
+        assert!(skipped("echo"));
+        assert!(skipped("fail"));
+        assert!(!skipped("pass"));
+    }
+
+    #[test]
+    fn test_every_task_run_once() {
+        let opts = test_opts("zexp-test-every-task-run-once");
+        let out_dir = opts.out_dir.clone();
+        let tasks: Vec<Task> = (0..100)
+            .map(|i| true_task(&format!("true_{}", i)))
+            .collect();
+
+        let mut runner = Runner::new(tasks, opts);
+        let csv_path = std::env::temp_dir().join("zexp-test-every-task-run-once.csv");
+        runner.set_csv(CsvWriter::create(&csv_path).unwrap());
+        let results = runner.run(4);
+        assert_eq!(results.len(), 100);
+        assert!(results.iter().all(|r| r.passed()));
+        assert_eq!(fs::read_dir(&out_dir).unwrap().count(), 100);
+        for i in 0..100 {
+            assert!(out_dir.join(format!("true_{}.out", i)).exists());
+        }
+
+        write_results(&out_dir.join("results.json"), &results).unwrap();
+        let manifest: serde_json::Value =
+            serde_json::from_reader(fs::File::open(out_dir.join("results.json")).unwrap()).unwrap();
+        assert_eq!(manifest.as_array().unwrap().len(), 100);
+        assert_eq!(manifest[0]["exit_code"], 0);
+        let read_back = read_results(&out_dir.join("results.json")).unwrap();
+        assert!(read_back.iter().all(|r| r.passed()));
+        // header and one row per task
+        assert_eq!(fs::read_to_string(csv_path).unwrap().lines().count(), 101);
+    }
+}
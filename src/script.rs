@@ -1,22 +1,582 @@
+use crate::executor::{DockerConfig, DockerExecutor, Executor, SlurmConfig, SlurmExecutor};
 use crate::tacle::TACLe;
-use serde::{Deserialize, Serialize};
+use crate::vars;
+use log::warn;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
 use std::fs::File;
+use std::io;
 use std::io::prelude::*;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 use toml::Table;
 
+/// the versions of the script format, given by `zexp_version`, this version of zexp can load
+pub const SUPPORTED_VERSIONS: RangeInclusive<i64> = 1..=1;
+
+/// Errors when loading a script
+#[derive(Debug)]
+pub enum ScriptError {
+    /// the script file cannot be opened or read
+    Io(String, io::Error),
+    /// the script is not valid TOML
+    Parse(String, toml::de::Error),
+    /// a required top-level key is absent or is not a string
+    MissingKey(String),
+    /// the command cannot be split into terms, e.g. because of an unclosed quote
+    InvalidCmd(String),
+    /// `zexp_version` is absent, is not an integer, or is not in `SUPPORTED_VERSIONS`
+    UnsupportedVersion(Option<toml::Value>),
+    /// `loaders` or `main_loader` is not well typed or names a loader that is not registered
+    InvalidLoader(String),
+    /// a `${env:NAME}` refers to an environment variable that is not set
+    UnsetEnv(String),
+    /// `include` is not an array of paths, or the included files include each other
+    InvalidInclude(String),
+    /// the table read by a loader, e.g. OTAWA, is absent or invalid, with the error
+    InvalidTable(String, String),
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ScriptError::Io(path, e) => write!(f, "failed to read script file {}: {}", path, e),
+            ScriptError::Parse(path, e) => {
+                write!(f, "TOML syntax error in script file {}: {}", path, e)
+            }
+            ScriptError::MissingKey(key) => write!(
+                f,
+                "missing required key {} in script, it must be a top-level string such as \
+                 {} = \"$app $arg\", or an array of them, before any [table]",
+                key, key
+            ),
+            ScriptError::InvalidCmd(e) => write!(f, "invalid CMD in script: {}", e),
+            ScriptError::UnsupportedVersion(version) => {
+                match version {
+                    None => write!(f, "missing zexp_version in script")?,
+                    Some(version) => write!(f, "unsupported zexp_version {} in script", version)?,
+                }
+                write!(
+                    f,
+                    ", this zexp supports versions {} to {}, e.g. zexp_version = {} at the top \
+                     of the script",
+                    SUPPORTED_VERSIONS.start(),
+                    SUPPORTED_VERSIONS.end(),
+                    SUPPORTED_VERSIONS.end()
+                )
+            }
+            ScriptError::InvalidLoader(e) => write!(f, "invalid loader in script: {}", e),
+            ScriptError::UnsetEnv(e) => write!(f, "in script: {}", e),
+            ScriptError::InvalidInclude(e) => write!(f, "invalid include in script: {}", e),
+            ScriptError::InvalidTable(table, e) => {
+                write!(f, "invalid {} table in script: {}", table, e)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ScriptError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ScriptError::Io(_, e) => Some(e),
+            ScriptError::Parse(_, e) => Some(e),
+            ScriptError::MissingKey(_)
+            | ScriptError::InvalidCmd(_)
+            | ScriptError::UnsupportedVersion(_)
+            | ScriptError::InvalidLoader(_)
+            | ScriptError::UnsetEnv(_)
+            | ScriptError::InvalidInclude(_)
+            | ScriptError::InvalidTable(_, _) => None,
+        }
+    }
+}
+
 /// One command, can be complete or incomplete,
 /// incomplete meaning that there are still some "$var" not replaced, complete otherwise
-#[derive(Debug, Clone)]
+///
+/// its fields are public to be (de)serialized, `Task::builder` is the way to build one
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Task {
     pub name: String,
     pub cmd: String,
     pub args: Vec<String>,
+    /// timeout of the task in seconds, `None` to use the default one
+    pub timeout_secs: Option<u64>,
+    /// environment variables set for the task, on top of the inherited ones
+    pub env: Vec<(String, String)>,
+    /// working directory of the task, `None` to run it in the current directory
+    pub cwd: Option<PathBuf>,
+    /// number of times the task is rerun when it fails, `None` to use the default one
+    pub retries: Option<u32>,
+    /// memory limit of the task in MB, `None` to use the default one
+    pub mem_limit_mb: Option<u64>,
+    /// benchset of the task, its outputs are written in a subdirectory of the same name
+    pub benchset: Option<String>,
+    /// labels of the task, e.g. `tool=otawa`, to group the results by label
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+    /// run the task once before, as `<name>.warmup`, e.g. to warm up the caches, the result
+    /// and the outputs of this run are dropped
+    #[serde(default)]
+    pub warmup: bool,
+    /// what the task is about, e.g. the description of its bench, only informational
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// estimated cost of the task, e.g. its duration in a previous run, the heaviest tasks are
+    /// started first so that no long task is left for the end, see `Runner`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub weight: Option<f64>,
 }
 
 impl Task {
+    /// a builder of a task, e.g. `Task::builder().cmd("sleep").arg("1").timeout(2).build()`
+    pub fn builder() -> TaskBuilder {
+        TaskBuilder::default()
+    }
+
     pub fn is_completed(&self) -> bool {
-        !self.cmd.contains("$") && !self.args.iter().any(|arg| arg.contains("$"))
+        self.unresolved().is_empty()
+    }
+
+    /// return an error naming the unresolved variables if the task is not completed
+    pub fn check_completed(&self) -> Result<(), String> {
+        let unresolved = self.unresolved();
+        if unresolved.is_empty() {
+            Ok(())
+        } else {
+            Err(format!(
+                "Command of task {} not completed, unresolved variables: {}",
+                self.name,
+                unresolved.join(", ")
+            ))
+        }
+    }
+
+    /// return the "$var" still present in the command or its environment, without duplicates
+    pub fn unresolved(&self) -> Vec<String> {
+        let mut res: Vec<String> = Vec::new();
+        let env_values = self.env.iter().map(|(_, value)| value);
+        for term in std::iter::once(&self.cmd)
+            .chain(self.args.iter())
+            .chain(env_values)
+        {
+            for var in vars::vars_of(term) {
+                if !res.contains(&var) {
+                    res.push(var);
+                }
+            }
+        }
+        res
+    }
+
+    /// turn the escaped `$$` into `$`, once the task is completed
+    fn unescape(&mut self) {
+        self.cmd = vars::unescape(&self.cmd);
+        for term in self
+            .args
+            .iter_mut()
+            .chain(self.env.iter_mut().map(|(_, v)| v))
+        {
+            *term = vars::unescape(term);
+        }
+    }
+
+    /// the command line of the task, prefixed by its environment,
+    /// quoted so that it can be pasted in a shell
+    pub fn command_line(&self) -> String {
+        let env = self
+            .env
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, shell_quote(value)));
+        let cmd = std::iter::once(&self.cmd)
+            .chain(self.args.iter())
+            .map(|term| shell_quote(term));
+        env.chain(cmd).collect::<Vec<String>>().join(" ")
+    }
+
+    /// the command line run by a shell that is replaced by the task, `KEY=value exec cmd args`:
+    /// the environment goes before `exec`, which would take `KEY=value` for the program
+    pub fn exec_line(&self) -> String {
+        let env = self
+            .env
+            .iter()
+            .map(|(key, value)| format!("{}={} ", key, shell_quote(value)));
+        let cmd = std::iter::once(&self.cmd)
+            .chain(self.args.iter())
+            .map(|term| shell_quote(term));
+        let cmd = cmd.collect::<Vec<String>>().join(" ");
+        format!("{}exec {}", env.collect::<String>(), cmd)
+    }
+}
+
+/// the file name of program, or program itself if it has none, e.g. `echo` for `/bin/echo`
+fn program_name(program: &str) -> String {
+    Path::new(program)
+        .file_name()
+        .map_or(program.to_string(), |name| {
+            name.to_string_lossy().to_string()
+        })
+}
+
+/// read and parse a script from reader, name is the one given in the errors
+fn parse_table<R: Read>(mut reader: R, name: &str) -> Result<Table, ScriptError> {
+    let mut content = String::new();
+    reader
+        .read_to_string(&mut content)
+        .map_err(|e| ScriptError::Io(name.to_string(), e))?;
+    content
+        .parse::<Table>()
+        .map_err(|e| ScriptError::Parse(name.to_string(), e))
+}
+
+/// read the script file at path, with the files it includes, see `resolve_includes`,
+/// stack is the chain of the files including it, to detect the cycles
+fn load_table(path: &Path, stack: &mut Vec<PathBuf>) -> Result<Table, ScriptError> {
+    let name = path.to_string_lossy().to_string();
+    let canonical = std::fs::canonicalize(path).map_err(|e| ScriptError::Io(name.clone(), e))?;
+    if stack.contains(&canonical) {
+        let chain: Vec<String> = stack
+            .iter()
+            .chain([&canonical])
+            .map(|path| path.to_string_lossy().to_string())
+            .collect();
+        return Err(ScriptError::InvalidInclude(format!(
+            "include cycle {}",
+            chain.join(" -> ")
+        )));
+    }
+    let file = File::open(path).map_err(|e| ScriptError::Io(name.clone(), e))?;
+    let table = parse_table(file, &name)?;
+    stack.push(canonical);
+    let dir = path.parent().unwrap_or(Path::new(""));
+    let res = resolve_includes(table, dir, &name, stack);
+    stack.pop();
+    res
+}
+
+/// make a relative `root_path` of the inline description `[TACLE.inline]` relative to dir,
+/// the directory of the script, as the one of a description file is relative to the file,
+/// once the environment variables it may start with are interpolated
+fn resolve_inline_root(config: &mut Table, dir: &Path) {
+    let Some(toml::Value::Table(tacle)) = config.get_mut("TACLE") else {
+        return;
+    };
+    let Some(toml::Value::Table(inline)) = tacle.get_mut("inline") else {
+        return;
+    };
+    if let Some(toml::Value::String(root_path)) = inline.get_mut("root_path") {
+        if Path::new(root_path).is_relative() {
+            *root_path = dir.join(&*root_path).to_string_lossy().into_owned();
+        }
+    }
+}
+
+/// the table with the files of its `include = ["common.toml", ...]` merged under it, in order,
+/// their paths are relative to dir, see `merge_tables`
+fn resolve_includes(
+    mut table: Table,
+    dir: &Path,
+    name: &str,
+    stack: &mut Vec<PathBuf>,
+) -> Result<Table, ScriptError> {
+    let Some(include) = table.remove("include") else {
+        return Ok(table);
+    };
+    let paths: Vec<String> = include.try_into().map_err(|_| {
+        ScriptError::InvalidInclude(format!("include must be an array of paths in {}", name))
+    })?;
+    let mut merged = Table::new();
+    for path in paths {
+        merge_tables(&mut merged, load_table(&dir.join(path), stack)?);
+    }
+    merge_tables(&mut merged, table);
+    Ok(merged)
+}
+
+/// merge over into base, the values of over replace the ones of base,
+/// except for tables that are merged key by key
+fn merge_tables(base: &mut Table, over: Table) {
+    for (key, value) in over {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base)), toml::Value::Table(over)) => merge_tables(base, over),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// the name of a task given by the `name_template` of a main loader, or default without it,
+/// the variables of the template are `$name` for default and the ones of vars,
+/// e.g. "${name}_$mode" gives "fft_a" for the default name fft and the mode a, the name is
+/// kept as is, the output files of the task are named after `runner::sanitize_filename`
+fn task_name(
+    template: Option<&str>,
+    default: String,
+    vars: &[(&str, String)],
+) -> Result<String, String> {
+    let Some(template) = template else {
+        return Ok(default);
+    };
+    let name = vars::substitute(template, &mut |var| {
+        let value = match var {
+            "$name" => &default,
+            _ => match vars.iter().find(|(name, _)| *name == var) {
+                Some((_, value)) => value,
+                None => {
+                    return Err(format!(
+                        "unknown variable {} in name_template {}",
+                        var, template
+                    ))
+                }
+            },
+        };
+        Ok(Some(vec![value.clone()]))
+    })?
+    .join(" ");
+    let name = vars::unescape(&name);
+    if name.is_empty() {
+        return Err(format!(
+            "name_template {} gives an empty task name",
+            template
+        ));
+    }
+    Ok(name)
+}
+
+/// A builder of `Task`, the fields not set get the default of the runner
+#[derive(Debug, Default)]
+pub struct TaskBuilder {
+    name: Option<String>,
+    cmd: String,
+    args: Vec<String>,
+    timeout_secs: Option<u64>,
+    env: Vec<(String, String)>,
+    cwd: Option<PathBuf>,
+    labels: BTreeMap<String, String>,
+    warmup: bool,
+    description: Option<String>,
+    weight: Option<f64>,
+}
+
+impl TaskBuilder {
+    /// the name of the task, the file name of its program by default
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// the program run by the task
+    pub fn cmd(mut self, cmd: impl Into<String>) -> Self {
+        self.cmd = cmd.into();
+        self
+    }
+
+    /// add an argument to the program
+    pub fn arg(mut self, arg: impl Into<String>) -> Self {
+        self.args.push(arg.into());
+        self
+    }
+
+    /// add arguments to the program
+    pub fn args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.args.extend(args.into_iter().map(Into::into));
+        self
+    }
+
+    /// set an environment variable for the task
+    pub fn env(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.env.push((key.into(), value.into()));
+        self
+    }
+
+    /// the working directory of the task
+    pub fn cwd(mut self, cwd: impl Into<PathBuf>) -> Self {
+        self.cwd = Some(cwd.into());
+        self
+    }
+
+    /// the timeout of the task, in seconds
+    pub fn timeout(mut self, secs: u64) -> Self {
+        self.timeout_secs = Some(secs);
+        self
+    }
+
+    /// add a label to the task
+    pub fn label(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.labels.insert(key.into(), value.into());
+        self
+    }
+
+    /// what the task is about, see `Task::description`
+    pub fn description(mut self, description: impl Into<String>) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    /// the estimated cost of the task, see `Task::weight`
+    pub fn weight(mut self, weight: f64) -> Self {
+        self.weight = Some(weight);
+        self
+    }
+
+    /// run the task once before, with its result dropped, see `Task::warmup`
+    pub fn warmup(mut self) -> Self {
+        self.warmup = true;
+        self
+    }
+
+    /// the task, an error if its command is empty
+    pub fn build(self) -> Result<Task, String> {
+        if self.cmd.is_empty() {
+            return Err("the command of the task is empty".to_string());
+        }
+        Ok(Task {
+            name: self.name.unwrap_or_else(|| program_name(&self.cmd)),
+            cmd: self.cmd,
+            args: self.args,
+            timeout_secs: self.timeout_secs,
+            env: self.env,
+            cwd: self.cwd,
+            retries: None,
+            mem_limit_mb: None,
+            benchset: None,
+            labels: self.labels,
+            warmup: self.warmup,
+            description: self.description,
+            weight: self.weight,
+        })
+    }
+}
+
+/// the names shared by several tasks, sorted, their outputs and results would collide
+pub fn duplicate_names(tasks: &[Task]) -> Vec<String> {
+    let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+    for task in tasks {
+        *counts.entry(&task.name).or_default() += 1;
+    }
+    counts
+        .into_iter()
+        .filter(|(_, count)| *count > 1)
+        .map(|(name, _)| name.to_string())
+        .collect()
+}
+
+/// rename the tasks whose name is shared by several tasks `<benchset>_<name>`,
+/// the ones without benchset keep their name
+pub fn rename_duplicates(tasks: &mut [Task]) {
+    let duplicates = duplicate_names(tasks);
+    for task in tasks.iter_mut() {
+        if let Some(benchset) = &task.benchset {
+            if duplicates.contains(&task.name) {
+                task.name = format!("{}_{}", benchset, task.name);
+            }
+        }
+    }
+}
+
+/// read the tasks written by `write_tasks`
+pub fn read_tasks(path: &Path) -> io::Result<Vec<Task>> {
+    let file = File::open(path)?;
+    Ok(serde_json::from_reader(file)?)
+}
+
+/// write tasks to `path`, in JSON
+pub fn write_tasks(path: &Path, tasks: &[Task]) -> io::Result<()> {
+    let file = File::create(path)?;
+    serde_json::to_writer_pretty(file, tasks)?;
+    Ok(())
+}
+
+/// quote a term for a POSIX shell, terms without special characters are kept as is
+pub fn shell_quote(term: &str) -> String {
+    let is_safe = |c: char| c.is_ascii_alphanumeric() || "_-./=:,+@%".contains(c);
+    if !term.is_empty() && term.chars().all(is_safe) {
+        term.to_string()
+    } else {
+        format!("'{}'", term.replace('\'', "'\\''"))
+    }
+}
+
+/// The commands of the HOOKS table, run around the tasks, named after their key
+#[derive(Debug, Clone, Default)]
+pub struct Hooks {
+    /// run once before the tasks, no task is run if it fails
+    pub before: Option<Task>,
+    /// run once after the tasks
+    pub after: Option<Task>,
+    /// run before each task, the task is not run if it fails
+    pub before_each: Option<Task>,
+    /// run after each task
+    pub after_each: Option<Task>,
+}
+
+/// The HOOKS table
+#[derive(Debug, Default, Deserialize)]
+struct HooksConfig {
+    before: Option<String>,
+    after: Option<String>,
+    before_each: Option<String>,
+    after_each: Option<String>,
+    /// timeout in seconds of each hook
+    #[serde(default, deserialize_with = "deserialize_timeout")]
+    timeout: Option<u64>,
+}
+
+/// parse a duration such as `90m`, a number with the unit s, m, h or d, in seconds without it
+pub fn parse_duration(duration: &str) -> Result<Duration, String> {
+    let (number, unit) = match duration.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => duration.split_at(i),
+        None => (duration, "s"),
+    };
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("expected a duration such as 90m, found {}", duration))?;
+    let secs = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => return Err(format!("unknown unit {}, expected s, m, h or d", unit)),
+    };
+    let secs = number
+        .checked_mul(secs)
+        .ok_or_else(|| format!("duration {} is too large", duration))?;
+    Ok(Duration::from_secs(secs))
+}
+
+/// a timeout in seconds, given as a number of seconds or as a duration such as "30m",
+/// see `parse_duration`
+fn deserialize_timeout<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<u64>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Timeout {
+        Secs(u64),
+        Duration(String),
     }
+    match Option::<Timeout>::deserialize(deserializer)? {
+        None => Ok(None),
+        Some(Timeout::Secs(secs)) => Ok(Some(secs)),
+        Some(Timeout::Duration(duration)) => parse_duration(&duration)
+            .map(|duration| Some(duration.as_secs()))
+            .map_err(serde::de::Error::custom),
+    }
+}
+
+/// One command of CMD, split into terms
+struct CmdTemplate {
+    /// suffix of the names of its tasks, `None` when CMD is a single command
+    label: Option<String>,
+    terms: Vec<String>,
 }
 
 /// The script has a main loader, the name of task
@@ -27,79 +587,483 @@ pub struct Script {
 }
 
 impl Script {
-    /// add a loader
-    pub fn register_loader<L: ConfigLoaderTrait + LoadableFromConfig + 'static>(&mut self) {
-        let new_loader = Box::new(L::from(self.script_config.clone()));
-        self.loaders.push(new_loader);
+    /// add a loader, fail if its table in the script is invalid
+    pub fn register_loader<L: ConfigLoaderTrait + LoadableFromConfig + 'static>(
+        &mut self,
+    ) -> Result<(), ScriptError> {
+        let new_loader = L::from(self.script_config.clone())?;
+        self.push_loader(Box::new(new_loader));
+        Ok(())
+    }
+
+    /// add loader after the registered ones, warning about the variables it provides that a
+    /// registered loader already provides, since it will not give them, see `provider`
+    fn push_loader(&mut self, loader: Box<dyn ConfigLoaderTrait>) {
+        for var in loader.provided_vars() {
+            if provider(&self.loaders, &var).is_some() {
+                warn!(
+                    "{} is provided by several loaders, using the first registered one",
+                    var
+                );
+            }
+        }
+        self.loaders.push(loader);
+    }
+
+    /// add main loader, fail if its table in the script is invalid
+    pub fn register_main_loader<ML: MainLoaderTrait + LoadableFromConfig + 'static>(
+        &mut self,
+    ) -> Result<(), ScriptError> {
+        let main_loader = ML::from(self.script_config.clone())?;
+        self.main_loader = Some(Box::new(main_loader));
+        Ok(())
+    }
+
+    /// let the benchsets to run be prefixes or close names of the actual ones, as
+    /// `fuzzy_benchset = true` in the TACLE table, to be called before `register_loaders`
+    pub fn set_fuzzy_benchset(&mut self) {
+        if let Some(toml::Value::Table(tacle)) = self.script_config.get_mut("TACLE") {
+            tacle.insert("fuzzy_benchset".to_string(), toml::Value::Boolean(true));
+        }
+    }
+
+    /// read the benchs of the TACLE table from the CSV file path, as `tacle_desc_csv = path`
+    /// in the TACLE table, to be called before `register_loaders`
+    pub fn set_tacle_desc_csv(&mut self, path: &str) -> Result<(), ScriptError> {
+        match self.script_config.get_mut("TACLE") {
+            Some(toml::Value::Table(tacle)) => {
+                tacle.insert(
+                    "tacle_desc_csv".to_string(),
+                    toml::Value::String(path.to_string()),
+                );
+                Ok(())
+            }
+            _ => Err(ScriptError::InvalidTable(
+                "TACLE".to_string(),
+                "the script has no [TACLE] table to read the benchs of the CSV into".to_string(),
+            )),
+        }
+    }
+
+    /// register the loaders declared by the script, taken from registry, see `loader_names`
+    pub fn register_loaders(&mut self, registry: &LoaderRegistry) -> Result<(), ScriptError> {
+        let (main_loader, loaders) = self.loader_names()?;
+        let loaders: Vec<&str> = loaders.iter().map(String::as_str).collect();
+        registry.register(self, &main_loader, &loaders)
+    }
+
+    /// the names of the main loader and of the loaders declared by the script:
+    /// its `main_loader`, or the one of its tables if it has none: GRID if it has a GRID table,
+    /// INPUTS if it has an INPUTS table, TACLE if it has a TACLE table, TASK otherwise, and its `loaders`, or OTAWA if it has
+    /// neither `main_loader` nor `loaders`, runs TACLE and has an OTAWA table
+    fn loader_names(&self) -> Result<(String, Vec<String>), ScriptError> {
+        let main_loader = match self.script_config.get("main_loader") {
+            Some(toml::Value::String(name)) => name.clone(),
+            Some(_) => {
+                return Err(ScriptError::InvalidLoader(
+                    "main_loader must be the name of a loader".to_string(),
+                ))
+            }
+            None if self.script_config.contains_key("GRID") => "GRID".to_string(),
+            None if self.script_config.contains_key("INPUTS") => "INPUTS".to_string(),
+            None if self.script_config.contains_key("TACLE") => "TACLE".to_string(),
+            None => "TASK".to_string(),
+        };
+        let loaders: Vec<String> = match self.script_config.get("loaders") {
+            Some(loaders) => loaders.clone().try_into().map_err(|_| {
+                ScriptError::InvalidLoader("loaders must be an array of loader names".to_string())
+            })?,
+            // a TACLe experiment without OTAWA options does not need its loader
+            None if !self.script_config.contains_key("main_loader")
+                && main_loader == "TACLE"
+                && self.script_config.contains_key("OTAWA") =>
+            {
+                vec!["OTAWA".to_string()]
+            }
+            None => Vec::new(),
+        };
+        Ok((main_loader, loaders))
     }
 
-    /// add main loader
-    pub fn register_main_loader<ML: MainLoaderTrait + LoadableFromConfig + 'static>(&mut self) {
-        self.main_loader = Some(Box::new(ML::from(self.script_config.clone())));
+    /// the config the script is run with: its included files merged and its environment
+    /// variables interpolated, with the `main_loader` and the `loaders` it defaults to
+    pub fn effective_config(&self) -> Result<Table, ScriptError> {
+        let (main_loader, loaders) = self.loader_names()?;
+        let mut config = self.script_config.clone();
+        config.remove("include");
+        config.insert("main_loader".to_string(), toml::Value::String(main_loader));
+        config.insert(
+            "loaders".to_string(),
+            toml::Value::Array(loaders.into_iter().map(toml::Value::String).collect()),
+        );
+        Ok(config)
     }
 
-    /// load the config from the script file
+    /// load the config from the script file, with the files it includes (see `load_table`)
     /// do not check the validity of the config (because loaders are not loaded yet)
-    pub fn from_file(path: &str) -> Self {
-        let mut script_file = File::open(path).expect("Error when openning the script file");
+    pub fn from_file(path: &str) -> Result<Self, ScriptError> {
+        let dir = Path::new(path).parent().unwrap_or(Path::new(""));
+        Self::from_config(load_table(Path::new(path), &mut Vec::new())?, dir)
+    }
 
-        let mut script_config = String::new();
-        script_file
-            .read_to_string(&mut script_config)
-            .expect("Error when reading script file");
+    /// load the config from reader, e.g. stdin, name is the one given in the errors,
+    /// the files it includes are relative to the current directory
+    /// do not check the validity of the config, as `from_file`
+    pub fn from_reader<R: Read>(reader: R, name: &str) -> Result<Self, ScriptError> {
+        let script_config = parse_table(reader, name)?;
+        let script_config = resolve_includes(script_config, Path::new(""), name, &mut Vec::new())?;
+        Self::from_config(script_config, Path::new(""))
+    }
 
-        let script_config = script_config
-            .parse::<Table>()
-            .expect("Error when parsing the script file, check you TOML syntax!");
+    /// the script of the config read by `from_file` or `from_reader`, from the directory dir
+    fn from_config(mut script_config: Table, dir: &Path) -> Result<Self, ScriptError> {
+        vars::interpolate_env_table(&mut script_config).map_err(ScriptError::UnsetEnv)?;
+        resolve_inline_root(&mut script_config, dir);
 
-        Self {
-            script_config: script_config,
+        let script = Self {
+            script_config,
             loaders: Vec::new(),
             main_loader: None,
+        };
+        script.check_version()?;
+        script.validate()?;
+        Ok(script)
+    }
+
+    /// check that the format of the script, given by `zexp_version`, is supported
+    fn check_version(&self) -> Result<(), ScriptError> {
+        match self.script_config.get("zexp_version") {
+            Some(toml::Value::Integer(version)) if SUPPORTED_VERSIONS.contains(version) => Ok(()),
+            version => Err(ScriptError::UnsupportedVersion(version.cloned())),
         }
     }
 
-    /// fill the command with all loaders, i.e. all static variables are replaced
-    fn fill_static_vars(&self) -> Vec<String> {
-        let mut static_vars = Vec::new();
-        let cmd = self.script_config["CMD"]
-            .as_str()
-            .expect("CMD must be a string");
-        let cmd = cmd.split_whitespace().collect::<Vec<&str>>();
-        for term in &cmd {
-            if term.starts_with("$") {
-                let mut provided = false;
-                for loader in &self.loaders {
-                    if loader.provided_vars().contains(&term.to_string()) {
-                        static_vars.extend(
-                            loader
-                                .get_terms(&term)
-                                .expect("variable claimed to be provided but not ?"),
-                        );
-                        provided = true;
+    /// check that the reserved keys are present and well typed
+    pub fn validate(&self) -> Result<(), ScriptError> {
+        self.cmd_templates().map(|_| ())
+    }
+
+    /// the command templates of CMD, split into terms following the quoting rules of a shell,
+    /// CMD is either a single command, or an array of commands given as strings or as tables
+    /// `{ label = "...", cmd = "..." }`, labelled by their index if they have no label
+    fn cmd_templates(&self) -> Result<Vec<CmdTemplate>, ScriptError> {
+        let split = |cmd: &str| {
+            let terms =
+                shell_words::split(cmd).map_err(|e| ScriptError::InvalidCmd(e.to_string()))?;
+            if terms.is_empty() {
+                return Err(ScriptError::InvalidCmd("empty command".to_string()));
+            }
+            Ok(terms)
+        };
+        let cmds = match self.script_config.get("CMD") {
+            Some(toml::Value::String(cmd)) => {
+                return Ok(vec![CmdTemplate {
+                    label: None,
+                    terms: split(cmd)?,
+                }])
+            }
+            Some(toml::Value::Array(cmds)) if !cmds.is_empty() => cmds,
+            Some(toml::Value::Array(_)) => {
+                return Err(ScriptError::InvalidCmd("CMD is an empty array".to_string()))
+            }
+            _ => return Err(ScriptError::MissingKey("CMD".to_string())),
+        };
+        let mut res: Vec<CmdTemplate> = Vec::new();
+        for (i, cmd) in cmds.iter().enumerate() {
+            let (label, cmd) = match cmd {
+                toml::Value::String(cmd) => (i.to_string(), cmd),
+                toml::Value::Table(table) => match (table.get("label"), table.get("cmd")) {
+                    (None, Some(toml::Value::String(cmd))) => (i.to_string(), cmd),
+                    (Some(toml::Value::String(label)), Some(toml::Value::String(cmd))) => {
+                        (label.clone(), cmd)
                     }
+                    _ => {
+                        return Err(ScriptError::InvalidCmd(format!(
+                            "command {} must have a string cmd and an optional string label",
+                            i
+                        )))
+                    }
+                },
+                _ => {
+                    return Err(ScriptError::InvalidCmd(format!(
+                        "command {} must be a string or a table",
+                        i
+                    )))
                 }
-                if !provided {
-                    static_vars.push(term.to_string());
-                }
+            };
+            if res.iter().any(|t| t.label.as_ref() == Some(&label)) {
+                return Err(ScriptError::InvalidCmd(format!(
+                    "several commands are labelled {}",
+                    label
+                )));
             }
+            res.push(CmdTemplate {
+                label: Some(label),
+                terms: split(cmd)?,
+            });
+        }
+        Ok(res)
+    }
+
+    /// fill the command templates with all loaders, i.e. all static variables are replaced
+    fn fill_static_vars(&self) -> Result<Vec<CmdTemplate>, String> {
+        self.cmd_templates()
+            .expect("CMD is checked by validate() when loading the script")
+            .into_iter()
+            .map(|template| {
+                let terms = template
+                    .terms
+                    .iter()
+                    .map(String::as_str)
+                    .collect::<Vec<&str>>();
+                Ok(CmdTemplate {
+                    terms: self.fill_static_terms(&terms)?,
+                    label: template.label,
+                })
+            })
+            .collect()
+    }
+
+    /// the environment given in the ENV table, with all static variables replaced
+    fn fill_static_env(&self) -> Result<Vec<(String, String)>, String> {
+        let env = match self.script_config.get("ENV") {
+            None => return Ok(Vec::new()),
+            Some(toml::Value::Table(env)) => env,
+            Some(_) => return Err("ENV must be a table".to_string()),
+        };
+        let mut res = Vec::new();
+        for (key, value) in env {
+            let value = value
+                .as_str()
+                .ok_or(format!("the value of {} in ENV must be a string", key))?;
+            let value = self.fill_static_terms(&[value])?;
+            res.push((key.clone(), value.join(" ")));
+        }
+        Ok(res)
+    }
+
+    /// replace the terms that are static variables with the terms given by their loader,
+    /// the other terms are kept as is
+    fn fill_static_terms(&self, terms: &[&str]) -> Result<Vec<String>, String> {
+        let mut expanding = Vec::new();
+        let mut static_vars = Vec::new();
+        for term in terms {
+            static_vars.extend(vars::substitute(term, &mut |var| {
+                self.expand_static_var(var, &mut expanding)
+            })?);
+        }
+        Ok(static_vars)
+    }
+
+    /// the terms of a static variable, in which the static variables are replaced in turn,
+    /// `None` if var is not a static variable
+    /// expanding are the variables being expanded, a variable referencing one of them is a cycle
+    fn expand_static_var(
+        &self,
+        var: &str,
+        expanding: &mut Vec<String>,
+    ) -> Result<Option<Vec<String>>, String> {
+        let loader = match provider(&self.loaders, var) {
+            // per-task variables are filled by the main loader, once the task is known
+            Some(loader) if !loader.is_per_task(var) => loader,
+            _ => return Ok(None),
+        };
+        if let Some(start) = expanding.iter().position(|v| v == var) {
+            let mut cycle = expanding[start..].to_vec();
+            cycle.push(var.to_string());
+            return Err(format!(
+                "Cyclic variable definition: {}",
+                cycle.join(" -> ")
+            ));
         }
-        static_vars
+        expanding.push(var.to_string());
+        let mut res = Vec::new();
+        for term in loader.get_terms(var)? {
+            res.extend(vars::substitute(&term, &mut |var| {
+                self.expand_static_var(var, expanding)
+            })?);
+        }
+        expanding.pop();
+        Ok(Some(res))
+    }
+
+    /// the config of the optional DOCKER table, to run the tasks in containers
+    pub fn docker_config(&self) -> Result<Option<DockerConfig>, String> {
+        self.executor_config("DOCKER")
+    }
+
+    /// the config of the optional SLURM table, to submit the tasks as jobs
+    pub fn slurm_config(&self) -> Result<Option<SlurmConfig>, String> {
+        self.executor_config("SLURM")
+    }
+
+    fn executor_config<C: serde::de::DeserializeOwned>(
+        &self,
+        table: &str,
+    ) -> Result<Option<C>, String> {
+        match self.script_config.get(table) {
+            None => Ok(None),
+            Some(config) => config
+                .clone()
+                .try_into()
+                .map(Some)
+                .map_err(|e| format!("invalid {} table: {}", table, e)),
+        }
+    }
+
+    /// the executor of the tasks given by the DOCKER or SLURM table, `None` to run them locally
+    pub fn gen_executor(&self) -> Result<Option<Box<dyn Executor>>, String> {
+        match (self.docker_config()?, self.slurm_config()?) {
+            (Some(_), Some(_)) => Err("a script cannot have both DOCKER and SLURM".to_string()),
+            (Some(docker), None) => Ok(Some(Box::new(DockerExecutor::new(docker)))),
+            (None, Some(slurm)) => Ok(Some(Box::new(SlurmExecutor::new(slurm)))),
+            (None, None) => Ok(None),
+        }
+    }
+
+    /// the hooks of the optional HOOKS table, their commands are split like CMD
+    /// and can use the static variables, they get the environment of the ENV table
+    pub fn gen_hooks(&self) -> Result<Hooks, String> {
+        let config: HooksConfig = match self.script_config.get("HOOKS") {
+            None => return Ok(Hooks::default()),
+            Some(hooks) => hooks
+                .clone()
+                .try_into()
+                .map_err(|e| format!("invalid HOOKS table: {}", e))?,
+        };
+        let env = self.fill_static_env()?;
+        let hook = |name: &str, cmd: &Option<String>| -> Result<Option<Task>, String> {
+            let Some(cmd) = cmd else {
+                return Ok(None);
+            };
+            let terms =
+                shell_words::split(cmd).map_err(|e| format!("invalid {} hook: {}", name, e))?;
+            let terms: Vec<&str> = terms.iter().map(String::as_str).collect();
+            let terms = self.fill_static_terms(&terms)?;
+            if terms.is_empty() {
+                return Err(format!("the {} hook is empty", name));
+            }
+            let mut task = Task {
+                name: name.to_string(),
+                cmd: terms[0].clone(),
+                args: terms[1..].to_vec(),
+                timeout_secs: config.timeout,
+                env: env.clone(),
+                cwd: None,
+                retries: None,
+                mem_limit_mb: None,
+                benchset: None,
+                labels: BTreeMap::new(),
+                warmup: false,
+                description: None,
+                weight: None,
+            };
+            task.check_completed()?;
+            task.unescape();
+            Ok(Some(task))
+        };
+        Ok(Hooks {
+            before: hook("before", &config.before)?,
+            after: hook("after", &config.after)?,
+            before_each: hook("before_each", &config.before_each)?,
+            after_each: hook("after_each", &config.after_each)?,
+        })
     }
 
+    /// generate the tasks of every command template, the names of the tasks of a template
+    /// given in an array of commands are suffixed by `__<label>`, and labelled `cmd=<label>`
     pub fn gen_cmd(&mut self) -> Result<Vec<Task>, String> {
-        let static_command = self.fill_static_vars();
-        let full_command = self
+        self.gen_cmd_iter()?.collect()
+    }
+
+    /// generate the tasks as `gen_cmd`, one at a time as the iterator is consumed, so that
+    /// the tasks of a large GRID are not all in memory at once, see `MainLoaderTrait::fill_iter`
+    ///
+    /// only the generation is lazy: `Runner` takes the whole list of tasks, and zexp collects
+    /// them anyway to check their names are unique, filter, weight or shuffle them
+    pub fn gen_cmd_iter(
+        &self,
+    ) -> Result<Box<dyn Iterator<Item = Result<Task, String>> + '_>, String> {
+        let env = self.fill_static_env()?;
+        let main_loader = self
             .main_loader
             .as_ref()
-            .expect("you must register a main loader before using the script")
-            .fill(&static_command)?;
-        Ok(full_command)
+            .expect("you must register a main loader before using the script");
+        for loader in &self.loaders {
+            if let Some(var) = loader
+                .provided_vars()
+                .into_iter()
+                .find(|var| RESERVED_VARS.contains(&var.as_str()))
+            {
+                return Err(format!(
+                    "{} is reserved, it is the one of each task and cannot be provided by a loader",
+                    var
+                ));
+            }
+        }
+        let mut templates = Vec::new();
+        for template in self.fill_static_vars()? {
+            let terms = match &template.label {
+                // $name is the one of the task, with the suffix of its template
+                Some(label) => vars::substitute_all(&template.terms, |var| {
+                    Ok((var == "$name").then(|| vec![format!("${{name}}__{}", label)]))
+                })?,
+                None => template.terms,
+            };
+            // e.g. a command of variables that all give no term
+            if terms.is_empty() {
+                return Err(match &template.label {
+                    Some(label) => format!("the command {} is empty", label),
+                    None => "the command is empty".to_string(),
+                });
+            }
+            templates.push((template.label, terms));
+        }
+        let tasks = templates.into_iter().flat_map(move |(label, terms)| {
+            main_loader
+                .fill_iter(terms, &self.loaders)
+                .map(move |task| -> Result<Task, String> {
+                    let mut task = task?;
+                    if let Some(label) = &label {
+                        task.name = format!("{}__{}", task.name, label);
+                        task.labels.insert("cmd".to_string(), label.clone());
+                    }
+                    Ok(task)
+                })
+        });
+        Ok(Box::new(tasks.map(move |task| {
+            let mut task = task?;
+            task.env = env.clone();
+            let unresolved = task.unresolved();
+            if !unresolved.is_empty() {
+                return Err(format!(
+                    "Environment of task {} not completed, unresolved variables: {}",
+                    task.name,
+                    unresolved.join(", ")
+                ));
+            }
+            task.unescape();
+            Ok(task)
+        })))
     }
 }
 
-pub trait LoadableFromConfig {
-    fn from(config: toml::Table) -> Self;
+pub trait LoadableFromConfig: Sized {
+    /// the loader configured by the script config, an error if its table is invalid
+    fn from(config: toml::Table) -> Result<Self, ScriptError>;
+}
+
+/// the variables filled by the main loaders for every task, with `TaskContext`,
+/// no loader can provide them
+pub const RESERVED_VARS: [&str; 2] = ["$name", "$benchset"];
+
+/// The identity of the task being generated by the main loader
+#[derive(Debug, Clone)]
+pub struct TaskContext {
+    /// name of the task, e.g. the bench name, the value of `$name`
+    pub name: String,
+    /// benchset of the task, if it has one, the value of `$benchset`
+    pub benchset: Option<String>,
 }
 
 pub trait ConfigLoaderTrait {
@@ -108,36 +1072,153 @@ pub trait ConfigLoaderTrait {
 
     /// return the possible terms to replace the given var_name
     fn get_terms(&self, var_name: &str) -> Result<Vec<String>, String>;
+
+    /// whether the value of var_name depends on the task, in this case it is not filled
+    /// with the static variables but by the main loader, with `get_terms_for`
+    fn is_per_task(&self, _var_name: &str) -> bool {
+        false
+    }
+
+    /// return the terms to replace the given var_name in the task given by context
+    fn get_terms_for(&self, var_name: &str, _context: &TaskContext) -> Result<Vec<String>, String> {
+        self.get_terms(var_name)
+    }
+}
+
+/// return the loader providing var, if several loaders provide it the first registered one wins
+fn provider<'a>(
+    loaders: &'a [Box<dyn ConfigLoaderTrait>],
+    var: &str,
+) -> Option<&'a dyn ConfigLoaderTrait> {
+    loaders
+        .iter()
+        .find(|l| l.provided_vars().iter().any(|v| v == var))
+        .map(|l| l.as_ref())
+}
+
+/// replace the per-task variables of cmd with the terms given by their loader for context,
+/// and the `RESERVED_VARS` with the ones of context
+pub fn fill_task_vars(
+    cmd: &[String],
+    loaders: &[Box<dyn ConfigLoaderTrait>],
+    context: &TaskContext,
+) -> Result<Vec<String>, String> {
+    vars::substitute_all(cmd, |var| match var {
+        "$name" => Ok(Some(vec![context.name.clone()])),
+        "$benchset" => Ok(context.benchset.clone().map(|benchset| vec![benchset])),
+        _ => match provider(loaders, var) {
+            Some(loader) if loader.is_per_task(var) => loader.get_terms_for(var, context).map(Some),
+            _ => Ok(None),
+        },
+    })
 }
 
 pub trait MainLoaderTrait {
-    /// Fill the "static" command with the last variables related to the main loader
+    /// Fill the "static" command with the last variables related to the main loader,
+    /// and the per-task variables of the loaders (see `fill_task_vars`)
     /// return all commands to run, if the command not complete after filling, return an error
-    fn fill(&self, cmd: &Vec<String>) -> Result<Vec<Task>, String>;
+    fn fill(
+        &self,
+        cmd: &[String],
+        loaders: &[Box<dyn ConfigLoaderTrait>],
+    ) -> Result<Vec<Task>, String>;
+
+    /// the tasks of `fill`, generated one at a time as the iterator is consumed,
+    /// the ones of `fill` by default, a loader of many tasks can generate them lazily,
+    /// for the users of `Script::gen_cmd_iter`, the runner is still given a list
+    fn fill_iter<'a>(
+        &'a self,
+        cmd: Vec<String>,
+        loaders: &'a [Box<dyn ConfigLoaderTrait>],
+    ) -> Box<dyn Iterator<Item = Result<Task, String>> + 'a> {
+        match self.fill(&cmd, loaders) {
+            Ok(tasks) => Box::new(tasks.into_iter().map(Ok)),
+            Err(e) => Box::new(std::iter::once(Err(e))),
+        }
+    }
+}
+
+/// the table name of config, read by the loader of the same name
+fn sub_table(config: &toml::Table, name: &str) -> Result<toml::Table, ScriptError> {
+    let error = |e: &str| ScriptError::InvalidTable(name.to_string(), e.to_string());
+    match config.get(name) {
+        Some(toml::Value::Table(table)) => Ok(table.clone()),
+        Some(_) => Err(error("it must be a table")),
+        None => Err(error(&format!(
+            "the script has no [{}] table, but uses its loader",
+            name
+        ))),
+    }
+}
+
+/// deserialize the table name read by a loader, the error tells the missing or bad field
+fn deserialize_table<T: DeserializeOwned>(
+    table: toml::Table,
+    name: &str,
+) -> Result<T, ScriptError> {
+    table.try_into().map_err(|e: toml::de::Error| {
+        // the message is followed by the key of the bad field on its own line
+        let e = e.to_string().trim().replace('\n', " ");
+        ScriptError::InvalidTable(name.to_string(), e)
+    })
+}
+
+/// a TOML value as a command term, strings are used without their quotes
+fn value_term(value: &toml::Value) -> String {
+    match value {
+        toml::Value::String(value) => value.clone(),
+        value => value.to_string(),
+    }
+}
+
+/// the properties given to OTAWA with `--add-prop`
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum OTAWAProps {
+    /// properties used verbatim, e.g. "otawa::FLOWFACTS_MANDATORY=true"
+    List(Vec<String>),
+    /// properties as key = value, passed as "key=value"
+    Table(toml::Table),
 }
 
 #[derive(Deserialize)]
 struct OTAWAConfigLoader {
-    PROVIDED_VARS: Vec<String>,
+    #[serde(rename = "PROVIDED_VARS")]
+    provided_vars: Vec<String>,
     app_path: String,
-    props: Vec<String>,
+    props: OTAWAProps,
+    /// level given to `--log`, no `--log` is emitted when it is empty
+    #[serde(default)]
     log_level: String,
+    /// never emit `--log`, whatever the log level
+    #[serde(default)]
+    no_log: bool,
+    /// directory of the flow facts files, `$otawa_flowfacts` is `<flowfacts_dir>/<task name>.ff`
+    flowfacts_dir: Option<PathBuf>,
+    /// do not check that app_path exists, e.g. when it is only on the machine running the tasks
+    #[serde(default)]
+    no_app_check: bool,
 }
 
 impl LoadableFromConfig for OTAWAConfigLoader {
-    fn from(config: toml::Table) -> Self {
+    fn from(config: toml::Table) -> Result<Self, ScriptError> {
         // get the corresponding sub-table
-        let otawa_sub_table = config["OTAWA"]
-            .as_table()
-            .expect("the OTAWA subtable should be a table")
-            .clone();
+        let otawa_sub_table = sub_table(&config, "OTAWA")?;
         // load the config with serde::Deserialize trait
-        otawa_sub_table.try_into().unwrap()
+        let otawa: OTAWAConfigLoader = deserialize_table(otawa_sub_table, "OTAWA")?;
+        let error = |e: String| ScriptError::InvalidTable("OTAWA".to_string(), e);
+        if otawa.app_path.is_empty() {
+            return Err(error("app_path is empty".to_string()));
+        }
+        if !otawa.no_app_check && !Path::new(&otawa.app_path).exists() {
+            return Err(error(format!("app_path {} does not exist", otawa.app_path)));
+        }
+        Ok(otawa)
     }
 }
 impl ConfigLoaderTrait for OTAWAConfigLoader {
     fn provided_vars(&self) -> Vec<String> {
-        return self.PROVIDED_VARS.clone();
+        self.provided_vars.clone()
     }
 
     fn get_terms(&self, var_name: &str) -> Result<Vec<String>, String> {
@@ -145,90 +1226,1757 @@ impl ConfigLoaderTrait for OTAWAConfigLoader {
             "$otawa_app" => Ok(vec![self.app_path.clone()]),
             "$otawa_opts" => {
                 let mut res = Vec::new();
-                for prop in &self.props {
+                let props: Vec<String> = match &self.props {
+                    OTAWAProps::List(props) => props.clone(),
+                    OTAWAProps::Table(props) => props
+                        .iter()
+                        .map(|(key, value)| format!("{}={}", key, value_term(value)))
+                        .collect(),
+                };
+                for prop in props {
                     res.push("--add-prop".to_string());
-                    res.push(prop.clone());
+                    res.push(prop);
+                }
+                if !self.no_log && !self.log_level.is_empty() {
+                    res.push("--log".to_string());
+                    res.push(self.log_level.clone());
                 }
-                res.push("--log".to_string());
-                res.push(self.log_level.clone());
                 Ok(res)
             }
-            _ => Err(format!("Unknown var_name: {}", var_name).to_string()),
+            "$otawa_flowfacts" => Err("$otawa_flowfacts depends on the task".to_string()),
+            _ => Err(format!("Unknown var_name: {}", var_name)),
+        }
+    }
+
+    fn is_per_task(&self, var_name: &str) -> bool {
+        var_name == "$otawa_flowfacts"
+    }
+
+    fn get_terms_for(&self, var_name: &str, context: &TaskContext) -> Result<Vec<String>, String> {
+        match (var_name, &self.flowfacts_dir) {
+            ("$otawa_flowfacts", Some(dir)) => {
+                let path = dir.join(format!("{}.ff", context.name));
+                Ok(vec![path.to_string_lossy().to_string()])
+            }
+            ("$otawa_flowfacts", None) => {
+                Err("$otawa_flowfacts is used but flowfacts_dir is not set".to_string())
+            }
+            _ => self.get_terms(var_name),
         }
     }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 struct TACLeConfigLoader {
-    PROVIDED_VARS: Vec<String>,
-    tacle_desc_path: String,
+    #[serde(rename = "PROVIDED_VARS")]
+    _provided_vars: Vec<String>,
+    /// path to the TACLe description, used when it is not inline
+    tacle_desc_path: Option<String>,
+    /// the TACLe description, in the `[TACLE.inline]` table of the script
+    inline: Option<Table>,
+    /// path to a CSV file of benchs, used instead of the other descriptions, see `TACLe::from_csv`
+    tacle_desc_csv: Option<String>,
+    /// the benchsets to run, all of them if empty or not given
+    #[serde(default)]
     tacle_run_benchset: Vec<String>,
+    /// a benchset of tacle_run_benchset that does not exist stands for the one it is a prefix of
+    /// or is the closest to, see `TACLe::match_benchset`
+    #[serde(default)]
+    fuzzy_benchset: bool,
+    /// timeout in seconds applied to every bench
+    #[serde(default, deserialize_with = "deserialize_timeout")]
+    timeout: Option<u64>,
+    /// directory the benches are run from
+    working_dir: Option<PathBuf>,
+    /// number of times a failing bench is rerun
+    retries: Option<u32>,
+    /// memory limit in MB of each task, on Linux only
+    mem_limit_mb: Option<u64>,
+    /// do not check that the exec of each bench exists
+    #[serde(default)]
+    no_exec_check: bool,
+    /// the name of the tasks, from `$bench`, `$benchset`, `$tacle_exec` and
+    /// `$tacle_entry_point`, the bench name by default, see `task_name`
+    name_template: Option<String>,
 }
 
 impl LoadableFromConfig for TACLeConfigLoader {
-    fn from(config: toml::Table) -> Self {
+    fn from(config: toml::Table) -> Result<Self, ScriptError> {
         // get the corresponding sub-table
-        let tacle_sub_table = config["TACLE"]
-            .as_table()
-            .expect("the OTAWA subtable should be a table")
-            .clone();
+        let tacle_sub_table = sub_table(&config, "TACLE")?;
         // load the config with serde::Deserialize trait
-        tacle_sub_table.try_into().unwrap()
+        deserialize_table(tacle_sub_table, "TACLE")
     }
 }
 
 impl MainLoaderTrait for TACLeConfigLoader {
-    fn fill(&self, cmd: &Vec<String>) -> Result<Vec<Task>, String> {
-        let tacle = TACLe::from_script(&self.tacle_desc_path);
-        let benchs = tacle.select_bench(&vec!["kernel".to_string()]);
-        let mut res = Vec::new();
-        for bench in &benchs {
-            let mut cmd = cmd.clone();
-            for term in cmd.iter_mut() {
-                match term.as_str() {
-                    "$tacle_exec" => *term = bench.exec.clone(),
-                    "$tacle_entry_point" => *term = bench.entry_point.clone(),
-                    _ => continue,
+    fn fill(
+        &self,
+        cmd: &[String],
+        loaders: &[Box<dyn ConfigLoaderTrait>],
+    ) -> Result<Vec<Task>, String> {
+        let tacle = match (&self.tacle_desc_csv, &self.inline, &self.tacle_desc_path) {
+            (Some(csv), _, _) => TACLe::from_csv(csv),
+            (None, Some(inline), _) => TACLe::from_table(inline.clone()),
+            (None, None, Some(path)) => TACLe::from_script(path),
+            (None, None, None) => {
+                return Err("TACLE needs a tacle_desc_path, a tacle_desc_csv \
+                    or an inline description in [TACLE.inline]"
+                    .to_string())
+            }
+        }
+        .map_err(|e| e.to_string())?;
+        let available = tacle.benchset_names();
+        let mut run_benchset = Vec::new();
+        let mut unknown = Vec::new();
+        for name in &self.tacle_run_benchset {
+            if available.contains(&name.as_str()) {
+                run_benchset.push(name.clone());
+                continue;
+            }
+            match tacle.match_benchset(name) {
+                Some(benchset) if self.fuzzy_benchset => {
+                    warn!("Benchset {} matched {}", name, benchset);
+                    run_benchset.push(benchset.to_string());
                 }
+                closest => unknown.push(match closest {
+                    Some(closest) => format!("{} (did you mean {}?)", name, closest),
+                    None => name.clone(),
+                }),
             }
+        }
+        if !unknown.is_empty() {
+            return Err(format!(
+                "Unknown benchsets {} in tacle_run_benchset, available benchsets are: {}",
+                unknown.join(", "),
+                available.join(", ")
+            ));
+        }
+        let benchs = tacle.select_bench(&run_benchset);
+        let missing = TACLe::missing_execs(&benchs);
+        if !self.no_exec_check && !missing.is_empty() {
+            let missing: Vec<String> = missing
+                .iter()
+                .map(|bench| format!("{} ({})", bench.name, bench.exec))
+                .collect();
+            return Err(format!(
+                "Executables of benchs not found: {}",
+                missing.join(", ")
+            ));
+        }
+        let mut res = Vec::new();
+        for bench in benchs {
+            // the extra terms of the benchset can use the TACLe and per-task variables
+            let cmd: Vec<String> = cmd.iter().chain(&bench.cmd_extra).cloned().collect();
+            let cmd = vars::substitute_all(&cmd, |var| {
+                Ok(match var {
+                    "$tacle_exec" => Some(vec![bench.exec.clone()]),
+                    "$tacle_entry_point" => Some(vec![bench.entry_point.clone()]),
+                    _ => None,
+                })
+            })?;
+            let name = task_name(
+                self.name_template.as_deref(),
+                bench.name.clone(),
+                &[
+                    ("$bench", bench.name.clone()),
+                    ("$benchset", bench.benchset.clone()),
+                    ("$tacle_exec", bench.exec.clone()),
+                    ("$tacle_entry_point", bench.entry_point.clone()),
+                ],
+            )?;
+            let context = TaskContext {
+                name: name.clone(),
+                benchset: Some(bench.benchset.clone()),
+            };
+            let cmd = fill_task_vars(&cmd, loaders, &context)?;
 
             let cmd = Task {
-                name: bench.name.clone(),
+                name,
                 cmd: cmd[0].clone(),
                 args: cmd[1..].to_vec(),
+                timeout_secs: self.timeout,
+                env: Vec::new(),
+                cwd: self.working_dir.clone(),
+                retries: self.retries,
+                mem_limit_mb: self.mem_limit_mb,
+                benchset: Some(bench.benchset.clone()),
+                labels: BTreeMap::from([("benchset".to_string(), bench.benchset.clone())]),
+                warmup: false,
+                description: bench.description.clone(),
+                weight: bench.weight,
             };
-            if !cmd.is_completed() {
-                return Err(format!("Command not completed: {:?}", cmd.args).to_string());
-            }
+            cmd.check_completed()?;
             res.push(cmd);
         }
         Ok(res)
     }
 }
 
-pub fn otawa_tacle_script(file_name: &str) -> Script {
-    let mut script = Script::from_file(file_name);
-    script.register_main_loader::<TACLeConfigLoader>();
-    script.register_loader::<OTAWAConfigLoader>();
-    script
+/// A main loader running the command once per combination of values of its variables
+#[derive(Debug, Deserialize)]
+struct GridConfigLoader {
+    /// timeout in seconds applied to every task
+    #[serde(default, deserialize_with = "deserialize_timeout")]
+    timeout: Option<u64>,
+    /// directory the tasks are run from
+    working_dir: Option<PathBuf>,
+    /// number of times a failing task is rerun
+    retries: Option<u32>,
+    /// memory limit in MB of each task, on Linux only
+    mem_limit_mb: Option<u64>,
+    /// the name of the tasks, from the "$var", named from their values by default,
+    /// see `task_name`
+    name_template: Option<String>,
+    /// the values taken by each "$var"
+    #[serde(flatten)]
+    vars: BTreeMap<String, Vec<toml::Value>>,
 }
 
-#[cfg(test)]
-mod test {
-    use super::*;
-    use log::debug;
-    #[test]
-    fn test_otawa_loader() {
-        use simplelog::*;
-        TermLogger::init(
-            LevelFilter::Trace,
-            Config::default(),
-            TerminalMode::Mixed,
-            ColorChoice::Auto,
+impl LoadableFromConfig for GridConfigLoader {
+    fn from(config: toml::Table) -> Result<Self, ScriptError> {
+        // get the corresponding sub-table
+        let grid_sub_table = sub_table(&config, "GRID")?;
+        // load the config with serde::Deserialize trait
+        let grid: GridConfigLoader = deserialize_table(grid_sub_table, "GRID")?;
+        if let Some(var) = grid.vars.keys().find(|var| !var.starts_with('$')) {
+            return Err(ScriptError::InvalidTable(
+                "GRID".to_string(),
+                format!("its variables must start with $, found {}", var),
+            ));
+        }
+        Ok(grid)
+    }
+}
+
+impl GridConfigLoader {
+    /// all the combinations of values, each one as a list of (var, value), the last variable
+    /// changing first, generated one at a time, `None` if they are too many to be counted
+    fn combinations(&self) -> Option<impl Iterator<Item = Vec<(&str, String)>>> {
+        let count = self
+            .vars
+            .values()
+            .try_fold(1usize, |count, values| count.checked_mul(values.len()))?;
+        Some((0..count).map(|mut index| {
+            let mut combination = Vec::with_capacity(self.vars.len());
+            // index is written in the mixed radix of the numbers of values
+            for (var, values) in self.vars.iter().rev() {
+                combination.push((var.as_str(), value_term(&values[index % values.len()])));
+                index /= values.len();
+            }
+            combination.reverse();
+            combination
+        }))
+    }
+
+    /// the task of the combination of values, from cmd
+    fn task(
+        &self,
+        cmd: &[String],
+        loaders: &[Box<dyn ConfigLoaderTrait>],
+        combination: Vec<(&str, String)>,
+    ) -> Result<Task, String> {
+        let cmd = vars::substitute_all(cmd, |var| {
+            Ok(combination
+                .iter()
+                .find(|(name, _)| *name == var)
+                .map(|(_, value)| vec![value.clone()]))
+        })?;
+
+        // the task is named from its values, e.g. "mode-a_threshold-4"
+        let name = combination
+            .iter()
+            .map(|(var, value)| format!("{}-{}", &var[1..], value))
+            .collect::<Vec<String>>()
+            .join("_");
+        let name = task_name(self.name_template.as_deref(), name, &combination)?;
+        let context = TaskContext {
+            name: name.clone(),
+            benchset: None,
+        };
+        let cmd = fill_task_vars(&cmd, loaders, &context)?;
+        let cmd = Task {
+            name,
+            cmd: cmd[0].clone(),
+            args: cmd[1..].to_vec(),
+            timeout_secs: self.timeout,
+            env: Vec::new(),
+            cwd: self.working_dir.clone(),
+            retries: self.retries,
+            mem_limit_mb: self.mem_limit_mb,
+            benchset: None,
+            // one label per variable, e.g. mode=a
+            labels: combination
+                .iter()
+                .map(|(var, value)| (var[1..].to_string(), value.clone()))
+                .collect(),
+            warmup: false,
+            description: None,
+            weight: None,
+        };
+        cmd.check_completed()?;
+        Ok(cmd)
+    }
+}
+
+impl MainLoaderTrait for GridConfigLoader {
+    fn fill(
+        &self,
+        cmd: &[String],
+        loaders: &[Box<dyn ConfigLoaderTrait>],
+    ) -> Result<Vec<Task>, String> {
+        self.fill_iter(cmd.to_vec(), loaders).collect()
+    }
+
+    fn fill_iter<'a>(
+        &'a self,
+        cmd: Vec<String>,
+        loaders: &'a [Box<dyn ConfigLoaderTrait>],
+    ) -> Box<dyn Iterator<Item = Result<Task, String>> + 'a> {
+        if let Some(var) = self
+            .vars
+            .keys()
+            .find(|var| RESERVED_VARS.contains(&var.as_str()))
+        {
+            return Box::new(std::iter::once(Err(format!(
+                "{} is reserved, it is the one of each task and cannot be a GRID variable",
+                var
+            ))));
+        }
+        match self.combinations() {
+            Some(combinations) => {
+                Box::new(combinations.map(move |combination| self.task(&cmd, loaders, combination)))
+            }
+            None => Box::new(std::iter::once(Err(
+                "GRID has too many combinations of values".to_string(),
+            ))),
+        }
+    }
+}
+
+/// A main loader running the command once per input file, bound to `$input`
+#[derive(Debug, Deserialize)]
+struct InputsConfigLoader {
+    /// a directory, whose files are the inputs, or a glob pattern matching them,
+    /// e.g. "inputs/*.txt"
+    path: String,
+    /// timeout in seconds applied to every task
+    #[serde(default, deserialize_with = "deserialize_timeout")]
+    timeout: Option<u64>,
+    /// directory the tasks are run from
+    working_dir: Option<PathBuf>,
+    /// number of times a failing task is rerun
+    retries: Option<u32>,
+    /// memory limit in MB of each task, on Linux only
+    mem_limit_mb: Option<u64>,
+    /// the name of the tasks, from `$input`, the file stem by default, see `task_name`
+    name_template: Option<String>,
+}
+
+impl LoadableFromConfig for InputsConfigLoader {
+    fn from(config: toml::Table) -> Result<Self, ScriptError> {
+        // get the corresponding sub-table
+        let inputs_sub_table = sub_table(&config, "INPUTS")?;
+        // load the config with serde::Deserialize trait
+        deserialize_table(inputs_sub_table, "INPUTS")
+    }
+}
+
+impl InputsConfigLoader {
+    /// the input files, in alphabetical order
+    fn inputs(&self) -> Result<Vec<PathBuf>, String> {
+        let path = Path::new(&self.path);
+        let mut inputs = if path.is_dir() {
+            std::fs::read_dir(path)
+                .map_err(|e| format!("cannot read the input directory {}: {}", self.path, e))?
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<Result<Vec<PathBuf>, io::Error>>()
+                .map_err(|e| format!("cannot read the input directory {}: {}", self.path, e))?
+        } else {
+            glob::glob(&self.path)
+                .map_err(|e| format!("invalid input pattern {}: {}", self.path, e))?
+                .collect::<Result<Vec<PathBuf>, glob::GlobError>>()
+                .map_err(|e| format!("cannot read the inputs {}: {}", self.path, e))?
+        };
+        inputs.retain(|input| input.is_file());
+        inputs.sort();
+        if inputs.is_empty() {
+            return Err(format!("no input file matches {}", self.path));
+        }
+        Ok(inputs)
+    }
+}
+
+impl MainLoaderTrait for InputsConfigLoader {
+    fn fill(
+        &self,
+        cmd: &[String],
+        loaders: &[Box<dyn ConfigLoaderTrait>],
+    ) -> Result<Vec<Task>, String> {
+        let mut res = Vec::new();
+        for input in self.inputs()? {
+            let input_str = input.to_string_lossy().to_string();
+            let cmd = vars::substitute_all(cmd, |var| {
+                Ok((var == "$input").then(|| vec![input_str.clone()]))
+            })?;
+
+            // the task is named from the file, e.g. "foo" for "inputs/foo.txt"
+            let name = input
+                .file_stem()
+                .map_or(input_str.clone(), |stem| stem.to_string_lossy().to_string());
+            let name = task_name(
+                self.name_template.as_deref(),
+                name,
+                &[("$input", input_str.clone())],
+            )?;
+            let context = TaskContext {
+                name: name.clone(),
+                benchset: None,
+            };
+            let cmd = fill_task_vars(&cmd, loaders, &context)?;
+            let cmd = Task {
+                name,
+                cmd: cmd[0].clone(),
+                args: cmd[1..].to_vec(),
+                timeout_secs: self.timeout,
+                env: Vec::new(),
+                cwd: self.working_dir.clone(),
+                retries: self.retries,
+                mem_limit_mb: self.mem_limit_mb,
+                benchset: None,
+                labels: BTreeMap::from([("input".to_string(), input_str)]),
+                warmup: false,
+                description: None,
+                weight: None,
+            };
+            cmd.check_completed()?;
+            res.push(cmd);
+        }
+        Ok(res)
+    }
+}
+
+/// A main loader running the command once, configured by the optional TASK table
+#[derive(Debug, Default, Deserialize)]
+struct SingleTaskLoader {
+    /// name of the task, the file name of the program by default
+    name: Option<String>,
+    /// timeout in seconds of the task
+    #[serde(default, deserialize_with = "deserialize_timeout")]
+    timeout: Option<u64>,
+    /// directory the task is run from
+    working_dir: Option<PathBuf>,
+    /// number of times the task is rerun if it fails
+    retries: Option<u32>,
+    /// memory limit in MB of the task, on Linux only
+    mem_limit_mb: Option<u64>,
+}
+
+impl LoadableFromConfig for SingleTaskLoader {
+    fn from(config: toml::Table) -> Result<Self, ScriptError> {
+        match config.get("TASK") {
+            None => Ok(SingleTaskLoader::default()),
+            // load the config with serde::Deserialize trait
+            Some(_) => deserialize_table(sub_table(&config, "TASK")?, "TASK"),
+        }
+    }
+}
+
+impl MainLoaderTrait for SingleTaskLoader {
+    fn fill(
+        &self,
+        cmd: &[String],
+        loaders: &[Box<dyn ConfigLoaderTrait>],
+    ) -> Result<Vec<Task>, String> {
+        let name = match &self.name {
+            Some(name) => name.clone(),
+            None => program_name(&cmd[0]),
+        };
+        let context = TaskContext {
+            name: name.clone(),
+            benchset: None,
+        };
+        let cmd = fill_task_vars(cmd, loaders, &context)?;
+        let cmd = Task {
+            name,
+            cmd: cmd[0].clone(),
+            args: cmd[1..].to_vec(),
+            timeout_secs: self.timeout,
+            env: Vec::new(),
+            cwd: self.working_dir.clone(),
+            retries: self.retries,
+            mem_limit_mb: self.mem_limit_mb,
+            benchset: None,
+            labels: BTreeMap::new(),
+            warmup: false,
+            description: None,
+            weight: None,
+        };
+        cmd.check_completed()?;
+        Ok(vec![cmd])
+    }
+}
+
+type LoaderConstructor = fn(Table) -> Result<Box<dyn ConfigLoaderTrait>, ScriptError>;
+type MainLoaderConstructor = fn(Table) -> Result<Box<dyn MainLoaderTrait>, ScriptError>;
+
+fn new_loader<L: ConfigLoaderTrait + LoadableFromConfig + 'static>(
+    config: Table,
+) -> Result<Box<dyn ConfigLoaderTrait>, ScriptError> {
+    Ok(Box::new(L::from(config)?))
+}
+
+fn new_main_loader<ML: MainLoaderTrait + LoadableFromConfig + 'static>(
+    config: Table,
+) -> Result<Box<dyn MainLoaderTrait>, ScriptError> {
+    Ok(Box::new(ML::from(config)?))
+}
+
+/// The loaders a script can declare by name, with `loaders = ["OTAWA"]` and
+/// `main_loader = "TACLE"` at its top level
+///
+/// the default registry has the loaders of zexp, named after the table they read:
+/// OTAWA, and the main loaders TACLE, GRID, INPUTS and TASK
+pub struct LoaderRegistry {
+    loaders: BTreeMap<String, LoaderConstructor>,
+    main_loaders: BTreeMap<String, MainLoaderConstructor>,
+}
+
+impl Default for LoaderRegistry {
+    fn default() -> Self {
+        let mut registry = Self::empty();
+        registry.add_loader::<OTAWAConfigLoader>("OTAWA");
+        registry.add_main_loader::<TACLeConfigLoader>("TACLE");
+        registry.add_main_loader::<GridConfigLoader>("GRID");
+        registry.add_main_loader::<InputsConfigLoader>("INPUTS");
+        registry.add_main_loader::<SingleTaskLoader>("TASK");
+        registry
+    }
+}
+
+impl LoaderRegistry {
+    /// a registry without any loader
+    pub fn empty() -> Self {
+        Self {
+            loaders: BTreeMap::new(),
+            main_loaders: BTreeMap::new(),
+        }
+    }
+
+    /// make the loader L available as name, replacing the one of the same name if any
+    pub fn add_loader<L: ConfigLoaderTrait + LoadableFromConfig + 'static>(&mut self, name: &str) {
+        self.loaders.insert(name.to_string(), new_loader::<L>);
+    }
+
+    /// make the main loader ML available as name, replacing the one of the same name if any
+    pub fn add_main_loader<ML: MainLoaderTrait + LoadableFromConfig + 'static>(
+        &mut self,
+        name: &str,
+    ) {
+        self.main_loaders
+            .insert(name.to_string(), new_main_loader::<ML>);
+    }
+
+    /// register in script the main loader and the loaders of the given names
+    pub fn register(
+        &self,
+        script: &mut Script,
+        main_loader: &str,
+        loaders: &[&str],
+    ) -> Result<(), ScriptError> {
+        let unknown = |kind: &str, name: &str, known: Vec<&String>| {
+            let known: Vec<&str> = known.into_iter().map(String::as_str).collect();
+            ScriptError::InvalidLoader(format!(
+                "unknown {} {}, the known ones are: {}",
+                kind,
+                name,
+                known.join(", ")
+            ))
+        };
+        let new_main = self.main_loaders.get(main_loader).ok_or_else(|| {
+            unknown(
+                "main loader",
+                main_loader,
+                self.main_loaders.keys().collect(),
+            )
+        })?;
+        let mut new_loaders = Vec::new();
+        for name in loaders {
+            let new = self
+                .loaders
+                .get(*name)
+                .ok_or_else(|| unknown("loader", name, self.loaders.keys().collect()))?;
+            new_loaders.push(new);
+        }
+        script.main_loader = Some(new_main(script.script_config.clone())?);
+        for new in new_loaders {
+            let loader = new(script.script_config.clone())?;
+            script.push_loader(loader);
+        }
+        Ok(())
+    }
+}
+
+/// a script running a command on the benchmarks of TACLe, see the TACLE and OTAWA tables
+pub fn otawa_tacle_script(mut script: Script) -> Result<Script, ScriptError> {
+    LoaderRegistry::default().register(&mut script, "TACLE", &["OTAWA"])?;
+    Ok(script)
+}
+
+/// a script sweeping over the values given in its GRID table
+pub fn grid_script(mut script: Script) -> Result<Script, ScriptError> {
+    LoaderRegistry::default().register(&mut script, "GRID", &[])?;
+    Ok(script)
+}
+
+/// a script running its command once, e.g. to get the timeout and the logging of zexp
+pub fn single_task_script(mut script: Script) -> Result<Script, ScriptError> {
+    LoaderRegistry::default().register(&mut script, "TASK", &[])?;
+    Ok(script)
+}
+
+/// load a script with the loaders it needs, see `Script::register_loaders`
+pub fn load_script(file_name: &str) -> Result<Script, ScriptError> {
+    let mut script = Script::from_file(file_name)?;
+    script.register_loaders(&LoaderRegistry::default())?;
+    Ok(script)
+}
+
+/// load a script read from reader, as `load_script`
+pub fn load_script_from_reader<R: Read>(reader: R, name: &str) -> Result<Script, ScriptError> {
+    let mut script = Script::from_reader(reader, name)?;
+    script.register_loaders(&LoaderRegistry::default())?;
+    Ok(script)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use log::debug;
+
+    /// copy the example script to a temporary file, pointing it to the TACLe description of this repo
+    fn example_script(file_name: &str) -> String {
+        let scripts_dir = concat!(env!("CARGO_MANIFEST_DIR"), "/scripts/otawa-tacle-exp");
+        let mut example = std::fs::read_to_string(format!("{}/example.toml", scripts_dir))
+            .unwrap()
+            .parse::<Table>()
+            .unwrap();
+        example["TACLE"].as_table_mut().unwrap().insert(
+            "tacle_desc_path".to_string(),
+            toml::Value::String(format!("{}/tacle.toml", scripts_dir)),
+        );
+        // the benchs are not compiled here, nor is OTAWA installed
+        example["TACLE"]
+            .as_table_mut()
+            .unwrap()
+            .insert("no_exec_check".to_string(), toml::Value::Boolean(true));
+        example["OTAWA"]
+            .as_table_mut()
+            .unwrap()
+            .insert("no_app_check".to_string(), toml::Value::Boolean(true));
+        let path = std::env::temp_dir().join(file_name);
+        std::fs::write(&path, example.to_string()).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    /// write a script of the supported version
+    fn write_script(path: &Path, content: &str) {
+        std::fs::write(path, format!("zexp_version = 1\n{}", content)).unwrap();
+    }
+
+    /// a TACLe loader on the description of this repo, running the given benchsets
+    fn tacle_loader(benchsets: &[&str]) -> TACLeConfigLoader {
+        TACLeConfigLoader {
+            _provided_vars: Vec::new(),
+            tacle_desc_path: Some(
+                concat!(
+                    env!("CARGO_MANIFEST_DIR"),
+                    "/scripts/otawa-tacle-exp/tacle.toml"
+                )
+                .to_string(),
+            ),
+            inline: None,
+            tacle_desc_csv: None,
+            tacle_run_benchset: benchsets.iter().map(|x| x.to_string()).collect(),
+            fuzzy_benchset: false,
+            timeout: None,
+            working_dir: None,
+            retries: None,
+            mem_limit_mb: None,
+            no_exec_check: true,
+            name_template: None,
+        }
+    }
+
+    #[test]
+    fn test_tasks_file() {
+        let path = std::env::temp_dir().join("zexp-test-tasks-file.json");
+        let tasks = tacle_loader(&["kernel"])
+            .fill(&["otawa", "$tacle_exec"].map(String::from), &[])
+            .unwrap();
+        write_tasks(&path, &tasks).unwrap();
+        let read_back = read_tasks(&path).unwrap();
+        assert_eq!(read_back.len(), tasks.len());
+        assert_eq!(read_back[0].command_line(), tasks[0].command_line());
+        assert_eq!(read_back[0].benchset.as_deref(), Some("kernel"));
+    }
+
+    #[test]
+    fn test_task_builder() {
+        let task = Task::builder()
+            .cmd("/bin/sleep")
+            .arg("1")
+            .args(["2", "3"])
+            .env("LANG", "C")
+            .cwd("/tmp")
+            .timeout(10)
+            .label("tool", "sleep")
+            .build()
+            .unwrap();
+        assert_eq!(task.name, "sleep");
+        assert_eq!(task.args, ["1", "2", "3"]);
+        assert_eq!(task.command_line(), "LANG=C /bin/sleep 1 2 3");
+        assert_eq!(task.cwd, Some(PathBuf::from("/tmp")));
+        assert_eq!(task.timeout_secs, Some(10));
+        assert_eq!(task.labels["tool"], "sleep");
+        assert_eq!(
+            Task::builder()
+                .name("nap")
+                .cmd("sleep")
+                .build()
+                .unwrap()
+                .name,
+            "nap"
+        );
+        assert!(Task::builder().name("nothing").build().is_err());
+    }
+
+    #[test]
+    fn test_shell_quote() {
+        assert_eq!(shell_quote("otawa::CACHE=a.xml"), "otawa::CACHE=a.xml");
+        assert_eq!(shell_quote(""), "''");
+        assert_eq!(shell_quote("foo bar"), "'foo bar'");
+        assert_eq!(shell_quote("it's"), "'it'\\''s'");
+    }
+
+    #[test]
+    fn test_unresolved_var() {
+        let loader = tacle_loader(&["kernel"]);
+        let cmd = ["otawa", "$tacle_exec", "--opt=$bogus_var"].map(String::from);
+        let err = loader.fill(&cmd, &[]).unwrap_err();
+        assert!(err.contains("$bogus_var"), "{}", err);
+        assert!(!err.contains("$tacle_exec"), "{}", err);
+    }
+
+    #[test]
+    fn test_unknown_benchset() {
+        let loader = tacle_loader(&["kernels"]);
+        let err = loader.fill(&["$tacle_exec".to_string()], &[]).unwrap_err();
+        assert!(err.contains("kernels (did you mean kernel?)"), "{}", err);
+        assert!(err.contains("available benchsets are: kernel"), "{}", err);
+
+        let loader = TACLeConfigLoader {
+            fuzzy_benchset: true,
+            ..tacle_loader(&["kernels", "kern"])
+        };
+        let tasks = loader.fill(&["$tacle_exec".to_string()], &[]).unwrap();
+        assert!(!tasks.is_empty());
+        assert!(tasks
+            .iter()
+            .all(|t| t.benchset.as_deref() == Some("kernel")));
+
+        let loader = TACLeConfigLoader {
+            fuzzy_benchset: true,
+            ..tacle_loader(&["xyz"])
+        };
+        let err = loader.fill(&["$tacle_exec".to_string()], &[]).unwrap_err();
+        assert!(err.contains("Unknown benchsets xyz in"), "{}", err);
+    }
+
+    #[test]
+    fn test_inline_tacle() {
+        let path = std::env::temp_dir().join("zexp-test-inline-tacle.toml");
+        write_script(
+            &path,
+            "main_loader = \"TACLE\"\nCMD = \"otawa $tacle_exec $tacle_entry_point\"\n\
+             [TACLE]\nPROVIDED_VARS = []\nno_exec_check = true\n\
+             [TACLE.inline]\nroot_path = \"/opt/tacle\"\n\
+             [[TACLE.inline.benchsets]]\nname = \"kernel\"\npath_from_root = \"kernel\"\n\
+             benchs = [{ name = \"fft\", exec = \"fft.elf\" }]\n",
+        );
+        let tasks = load_script(path.to_str().unwrap())
+            .unwrap()
+            .gen_cmd()
+            .unwrap();
+        assert_eq!(tasks.len(), 1);
+        assert_eq!(tasks[0].args, ["/opt/tacle/kernel/fft.elf", "main"]);
+        // nor is OTAWA when it has no table, even if TACLE is the default main loader
+        let content = std::fs::read_to_string(&path).unwrap();
+        std::fs::write(&path, content.replace("main_loader = \"TACLE\"\n", "")).unwrap();
+        let tasks = load_script(path.to_str().unwrap())
+            .unwrap()
+            .gen_cmd()
+            .unwrap();
+        assert_eq!(tasks.len(), 1);
+        // a relative root_path is relative to the script, not to where zexp is run
+        let content = std::fs::read_to_string(&path).unwrap();
+        std::fs::write(&path, content.replace("\"/opt/tacle\"", "\"tacle\"")).unwrap();
+        let tasks = load_script(path.to_str().unwrap())
+            .unwrap()
+            .gen_cmd()
+            .unwrap();
+        let exec = std::env::temp_dir().join("tacle/kernel/fft.elf");
+        assert_eq!(tasks[0].args[0], exec.to_str().unwrap());
+
+        let loader = TACLeConfigLoader {
+            tacle_desc_path: None,
+            ..tacle_loader(&[])
+        };
+        let err = loader.fill(&["$tacle_exec".to_string()], &[]).unwrap_err();
+        assert!(err.contains("inline"), "{}", err);
+    }
+
+    #[test]
+    fn test_duplicate_names() {
+        let desc = std::env::temp_dir().join("zexp-test-duplicate-names-tacle.toml");
+        std::fs::write(
+            &desc,
+            "root_path = \"/opt/tacle\"\n\
+             [[benchsets]]\nname = \"kernel\"\npath_from_root = \"kernel\"\n\
+             benchs = [{ name = \"fft\", exec = \"fft.elf\" }, { name = \"md5\", exec = \"md5.elf\" }]\n\
+             [[benchsets]]\nname = \"app\"\npath_from_root = \"app\"\n\
+             benchs = [{ name = \"fft\", exec = \"fft.elf\" }]\n",
+        )
+        .unwrap();
+        let loader = TACLeConfigLoader {
+            tacle_desc_path: Some(desc.to_str().unwrap().to_string()),
+            ..tacle_loader(&["kernel", "app"])
+        };
+        let mut tasks = loader.fill(&["$tacle_exec".to_string()], &[]).unwrap();
+        assert_eq!(duplicate_names(&tasks), ["fft"]);
+
+        rename_duplicates(&mut tasks);
+        assert!(duplicate_names(&tasks).is_empty());
+        let mut names: Vec<&str> = tasks.iter().map(|t| t.name.as_str()).collect();
+        names.sort();
+        assert_eq!(names, ["app_fft", "kernel_fft", "md5"]);
+    }
+
+    #[test]
+    fn test_benchset_cmd_extra() {
+        let desc = std::env::temp_dir().join("zexp-test-benchset-cmd-extra-tacle.toml");
+        std::fs::write(
+            &desc,
+            "root_path = \"/opt/tacle\"\n\
+             [[benchsets]]\nname = \"kernel\"\npath_from_root = \"kernel\"\n\
+             benchs = [{ name = \"fft\", exec = \"fft.elf\", entry_point = \"main\" }]\n\
+             [[benchsets]]\nname = \"app\"\npath_from_root = \"app\"\n\
+             cmd_extra = [\"--add-prop\", \"otawa::ENTRY=$tacle_entry_point\"]\n\
+             benchs = [{ name = \"lift\", exec = \"lift.elf\", entry_point = \"main\" }]\n",
+        )
+        .unwrap();
+        let loader = TACLeConfigLoader {
+            tacle_desc_path: Some(desc.to_str().unwrap().to_string()),
+            ..tacle_loader(&["kernel", "app"])
+        };
+        let cmd = ["otawa", "$tacle_exec", "--log"].map(String::from);
+        let tasks = loader.fill(&cmd, &[]).unwrap();
+        let fft = tasks.iter().find(|t| t.name == "fft").unwrap();
+        let lift = tasks.iter().find(|t| t.name == "lift").unwrap();
+        assert_eq!(fft.args, ["/opt/tacle/kernel/fft.elf", "--log"]);
+        assert_eq!(
+            lift.args,
+            [
+                "/opt/tacle/app/lift.elf",
+                "--log",
+                "--add-prop",
+                "otawa::ENTRY=main"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_missing_execs() {
+        let loader = TACLeConfigLoader {
+            no_exec_check: false,
+            ..tacle_loader(&["kernel"])
+        };
+        let err = loader.fill(&["$tacle_exec".to_string()], &[]).unwrap_err();
+        assert!(err.contains("binarysearch ("), "{}", err);
+    }
+
+    #[test]
+    fn test_script_errors() {
+        let err = Script::from_file("/nonexistent/script.toml").err().unwrap();
+        assert!(matches!(err, ScriptError::Io(..)));
+
+        let path = std::env::temp_dir().join("zexp-test-script-errors.toml");
+        write_script(&path, "CMD = ");
+        let err = Script::from_file(path.to_str().unwrap()).err().unwrap();
+        assert!(matches!(err, ScriptError::Parse(..)));
+
+        write_script(&path, "[OTAWA]");
+        let err = Script::from_file(path.to_str().unwrap()).err().unwrap();
+        assert!(matches!(err, ScriptError::MissingKey(ref key) if key == "CMD"));
+
+        write_script(&path, "CMD = 1");
+        let err = Script::from_file(path.to_str().unwrap()).err().unwrap();
+        assert!(matches!(err, ScriptError::MissingKey(ref key) if key == "CMD"));
+
+        write_script(&path, "CMD = [\"otawa\", 1]");
+        let err = Script::from_file(path.to_str().unwrap()).err().unwrap();
+        assert!(matches!(err, ScriptError::InvalidCmd(_)));
+
+        write_script(&path, "CMD = []");
+        let err = Script::from_file(path.to_str().unwrap()).err().unwrap();
+        assert!(matches!(err, ScriptError::InvalidCmd(_)));
+
+        write_script(&path, "CMD = \"otawa '$otawa_app\"");
+        let err = Script::from_file(path.to_str().unwrap()).err().unwrap();
+        assert!(matches!(err, ScriptError::InvalidCmd(_)));
+    }
+
+    #[test]
+    fn test_script_version() {
+        let path = std::env::temp_dir().join("zexp-test-script-version.toml");
+        std::fs::write(&path, "CMD = \"true\"").unwrap();
+        let err = Script::from_file(path.to_str().unwrap()).err().unwrap();
+        assert!(matches!(err, ScriptError::UnsupportedVersion(None)));
+        assert!(err.to_string().contains("zexp_version = 1"), "{}", err);
+
+        std::fs::write(&path, "zexp_version = 2\nCMD = \"true\"").unwrap();
+        let err = Script::from_file(path.to_str().unwrap()).err().unwrap();
+        assert!(matches!(err, ScriptError::UnsupportedVersion(Some(_))));
+        assert!(
+            err.to_string().contains("unsupported zexp_version 2"),
+            "{}",
+            err
+        );
+
+        std::fs::write(&path, "zexp_version = \"1\"\nCMD = \"true\"").unwrap();
+        let err = Script::from_file(path.to_str().unwrap()).err().unwrap();
+        assert!(matches!(err, ScriptError::UnsupportedVersion(Some(_))));
+
+        write_script(&path, "CMD = \"true\"");
+        assert!(Script::from_file(path.to_str().unwrap()).is_ok());
+    }
+
+    #[test]
+    fn test_quoted_cmd() {
+        let path = std::env::temp_dir().join("zexp-test-quoted-cmd.toml");
+        write_script(
+            &path,
+            r#"CMD = '''"/opt/my tools/run" --prop="foo $mode" 'a b' c\ d'''
+[GRID]
+"$mode" = ["bar"]
+"#,
+        );
+        let tasks = load_script(path.to_str().unwrap())
+            .unwrap()
+            .gen_cmd()
+            .unwrap();
+        assert_eq!(tasks[0].cmd, "/opt/my tools/run");
+        assert_eq!(tasks[0].args, ["--prop=foo bar", "a b", "c d"]);
+    }
+
+    #[test]
+    fn test_env() {
+        let path = std::env::temp_dir().join("zexp-test-env.toml");
+        let mut script = std::fs::read_to_string(example_script("zexp-test-env-example.toml"))
+            .unwrap()
+            .parse::<Table>()
+            .unwrap();
+        let env = "OTAWA_APP = \"$otawa_app\"\nLANG = \"C\""
+            .parse::<Table>()
+            .unwrap();
+        script.insert("ENV".to_string(), toml::Value::Table(env));
+        std::fs::write(&path, script.to_string()).unwrap();
+
+        let tasks = otawa_tacle_script(Script::from_file(path.to_str().unwrap()).unwrap())
+            .unwrap()
+            .gen_cmd()
+            .unwrap();
+        let app = tasks[0].cmd.clone();
+        assert!(tasks.iter().all(|t| t.env
+            == [
+                ("LANG".to_string(), "C".to_string()),
+                ("OTAWA_APP".to_string(), app.clone())
+            ]));
+    }
+
+    #[test]
+    fn test_executor_config() {
+        let path = std::env::temp_dir().join("zexp-test-docker-config.toml");
+        write_script(&path, "CMD = \"true\"\n");
+        let script = load_script(path.to_str().unwrap()).unwrap();
+        assert!(script.docker_config().unwrap().is_none());
+
+        write_script(
+            &path,
+            "CMD = \"true\"\n[DOCKER]\nimage = \"otawa:2.0\"\nvolumes = [\"/opt:/opt\"]\n",
+        );
+        let script = load_script(path.to_str().unwrap()).unwrap();
+        let config = script.docker_config().unwrap().unwrap();
+        assert_eq!(config.image, "otawa:2.0");
+        assert_eq!(config.volumes, ["/opt:/opt"]);
+
+        write_script(&path, "CMD = \"true\"\n[DOCKER]\nvolumes = []\n");
+        let script = load_script(path.to_str().unwrap()).unwrap();
+        assert!(script.docker_config().is_err());
+
+        write_script(
+            &path,
+            "CMD = \"true\"\n[SLURM]\npartition = \"short\"\ncpus = 4\n",
+        );
+        let script = load_script(path.to_str().unwrap()).unwrap();
+        let config = script.slurm_config().unwrap().unwrap();
+        assert_eq!(config.partition.as_deref(), Some("short"));
+        assert_eq!(config.cpus, Some(4));
+        assert!(script.gen_executor().unwrap().is_some());
+
+        write_script(
+            &path,
+            "CMD = \"true\"\n[SLURM]\n[DOCKER]\nimage = \"otawa\"\n",
+        );
+        let script = load_script(path.to_str().unwrap()).unwrap();
+        assert!(script.gen_executor().is_err());
+    }
+
+    #[test]
+    fn test_hooks() {
+        let path = std::env::temp_dir().join("zexp-test-hooks.toml");
+        write_script(&path, "CMD = \"true\"");
+        let hooks = load_script(path.to_str().unwrap())
+            .unwrap()
+            .gen_hooks()
+            .unwrap();
+        assert!(hooks.before.is_none() && hooks.after_each.is_none());
+
+        write_script(
+            &path,
+            "CMD = \"true\"\n\
+             [ENV]\nLANG = \"C\"\n\
+             [HOOKS]\nbefore = \"mount 'my disk'\"\nafter_each = \"rm -f $$TMP\"\ntimeout = 5\n",
+        );
+        let hooks = load_script(path.to_str().unwrap())
+            .unwrap()
+            .gen_hooks()
+            .unwrap();
+        let before = hooks.before.unwrap();
+        assert_eq!(before.name, "before");
+        assert_eq!(before.args, ["my disk"]);
+        assert_eq!(before.timeout_secs, Some(5));
+        assert_eq!(before.env, [("LANG".to_string(), "C".to_string())]);
+        assert_eq!(hooks.after_each.unwrap().args, ["-f", "$TMP"]);
+        assert!(hooks.after.is_none());
+
+        write_script(
+            &path,
+            "CMD = \"true\"\n[HOOKS]\nbefore = \"setup $unknown\"\n",
+        );
+        let err = load_script(path.to_str().unwrap())
+            .unwrap()
+            .gen_hooks()
+            .unwrap_err();
+        assert!(err.contains("$unknown"), "{}", err);
+    }
+
+    #[test]
+    fn test_grid_loader() {
+        let path = std::env::temp_dir().join("zexp-test-grid-loader.toml");
+        write_script(
+            &path,
+            "CMD = \"tool --threshold $threshold $mode\"\n\
+             [GRID]\n\
+             timeout = 10\n\
+             \"$threshold\" = [1, 2, 4, 8]\n\
+             \"$mode\" = [\"a\", \"b\"]\n",
+        );
+        let tasks = load_script(path.to_str().unwrap())
+            .unwrap()
+            .gen_cmd()
+            .unwrap();
+        assert_eq!(tasks.len(), 8);
+        let task = tasks
+            .iter()
+            .find(|t| t.name == "mode-b_threshold-4")
+            .unwrap();
+        assert_eq!(task.cmd, "tool");
+        assert_eq!(task.args, ["--threshold", "4", "b"]);
+        assert_eq!(task.timeout_secs, Some(10));
+        assert_eq!(task.labels["mode"], "b");
+        assert_eq!(task.labels["threshold"], "4");
+    }
+
+    #[test]
+    fn test_lazy_grid() {
+        let path = std::env::temp_dir().join("zexp-test-lazy-grid.toml");
+        let values: Vec<String> = (0..1000).map(|i| i.to_string()).collect();
+        let values = values.join(", ");
+        // a billion tasks, only the first ones are generated
+        write_script(
+            &path,
+            &format!(
+                "CMD = \"tool $a $b $c\"\n[GRID]\n\"$a\" = [{0}]\n\"$b\" = [{0}]\n\"$c\" = [{0}]\n",
+                values
+            ),
+        );
+        let script = load_script(path.to_str().unwrap()).unwrap();
+        let names: Vec<String> = script
+            .gen_cmd_iter()
+            .unwrap()
+            .take(3)
+            .map(|task| task.unwrap().name)
+            .collect();
+        assert_eq!(names, ["a-0_b-0_c-0", "a-0_b-0_c-1", "a-0_b-0_c-2"]);
+
+        // in the same order as before, the last variable changing first
+        write_script(
+            &path,
+            "CMD = \"tool $a $b\"\n[GRID]\n\"$a\" = [1, 2]\n\"$b\" = [\"x\", \"y\"]\n",
+        );
+        let names: Vec<String> = load_script(path.to_str().unwrap())
+            .unwrap()
+            .gen_cmd()
+            .unwrap()
+            .into_iter()
+            .map(|task| task.name)
+            .collect();
+        assert_eq!(names, ["a-1_b-x", "a-1_b-y", "a-2_b-x", "a-2_b-y"]);
+    }
+
+    #[test]
+    fn test_task_name() {
+        let vars = [("$mode", "a".to_string()), ("$threshold", "4".to_string())];
+        let name = |template| task_name(template, "fft".to_string(), &vars);
+        assert_eq!(name(None).unwrap(), "fft");
+        assert_eq!(name(Some("${name}_${mode}_$threshold")).unwrap(), "fft_a_4");
+        assert_eq!(name(Some("${name}-t${threshold}")).unwrap(), "fft-t4");
+        // as in the commands, `_` is part of the name of a variable
+        assert!(name(Some("$name_$mode"))
+            .unwrap_err()
+            .contains("unknown variable $name_"));
+        // sanitized in the names of the output files only
+        assert_eq!(name(Some("$name/$mode: $$x")).unwrap(), "fft/a: $x");
+        assert!(name(Some("$bench"))
+            .unwrap_err()
+            .contains("unknown variable $bench"));
+        assert!(task_name(Some("$name"), String::new(), &vars).is_err());
+    }
+
+    #[test]
+    fn test_name_template() {
+        let path = std::env::temp_dir().join("zexp-test-name-template.toml");
+        write_script(
+            &path,
+            "CMD = \"tool $mode $dir\"\n\
+             [GRID]\n\
+             name_template = \"run_${mode}_$dir\"\n\
+             \"$mode\" = [\"a\", \"b\"]\n\
+             \"$dir\" = [\"/tmp\"]\n",
+        );
+        let tasks = load_script(path.to_str().unwrap())
+            .unwrap()
+            .gen_cmd()
+            .unwrap();
+        let names: Vec<&str> = tasks.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(names, ["run_a_/tmp", "run_b_/tmp"]);
+    }
+
+    #[test]
+    fn test_inputs_loader() {
+        let dir = std::env::temp_dir().join("zexp-test-inputs-loader");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        for file in ["b.txt", "a.txt", "c.csv"] {
+            std::fs::write(dir.join(file), "").unwrap();
+        }
+        let path = std::env::temp_dir().join("zexp-test-inputs-loader.toml");
+        let load = |inputs: &Path| {
+            write_script(
+                &path,
+                &format!(
+                    "CMD = \"tool --in $input\"\n[INPUTS]\npath = \"{}\"\ntimeout = 10\n",
+                    inputs.display()
+                ),
+            );
+            load_script(path.to_str().unwrap()).unwrap().gen_cmd()
+        };
+        // a directory: all its files, but not its subdirectories
+        let tasks = load(&dir).unwrap();
+        let names: Vec<&str> = tasks.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(names, ["a", "b", "c"]);
+        let input = dir.join("a.txt").to_string_lossy().to_string();
+        assert_eq!(tasks[0].args, ["--in", input.as_str()]);
+        assert_eq!(tasks[0].labels["input"], input);
+        assert_eq!(tasks[0].timeout_secs, Some(10));
+        // a glob pattern
+        let tasks = load(&dir.join("*.txt")).unwrap();
+        let names: Vec<&str> = tasks.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(names, ["a", "b"]);
+        let err = load(&dir.join("*.json")).err().unwrap();
+        assert!(err.contains("no input file matches"));
+    }
+
+    #[test]
+    fn test_cmd_array() {
+        let path = std::env::temp_dir().join("zexp-test-cmd-array.toml");
+        write_script(
+            &path,
+            "CMD = [\"tool_a $x\", { label = \"b\", cmd = \"tool_b $x\" }, \"tool_c $x\"]\n\
+             [GRID]\n\
+             \"$x\" = [1, 2, 3, 4]\n",
+        );
+        let tasks = load_script(path.to_str().unwrap())
+            .unwrap()
+            .gen_cmd()
+            .unwrap();
+        assert_eq!(tasks.len(), 3 * 4);
+        let task = |name: &str| tasks.iter().find(|t| t.name == name).unwrap();
+        assert_eq!(task("x-1__0").cmd, "tool_a");
+        assert_eq!(task("x-2__b").cmd, "tool_b");
+        assert_eq!(task("x-4__2").args, ["4"]);
+        assert_eq!(task("x-2__b").labels["cmd"], "b");
+
+        write_script(&path, "CMD = [\"a\", { label = \"0\", cmd = \"b\" }]");
+        let err = Script::from_file(path.to_str().unwrap()).err().unwrap();
+        assert!(err.to_string().contains("labelled 0"), "{}", err);
+    }
+
+    /// a loader giving a flow fact file per task
+    struct FlowFactsLoader;
+
+    impl ConfigLoaderTrait for FlowFactsLoader {
+        fn provided_vars(&self) -> Vec<String> {
+            vec!["$flowfacts".to_string()]
+        }
+
+        fn get_terms(&self, var_name: &str) -> Result<Vec<String>, String> {
+            Err(format!("{} depends on the task", var_name))
+        }
+
+        fn is_per_task(&self, _var_name: &str) -> bool {
+            true
+        }
+
+        fn get_terms_for(
+            &self,
+            _var_name: &str,
+            context: &TaskContext,
+        ) -> Result<Vec<String>, String> {
+            Ok(vec![format!("ff/{}.ff", context.name)])
+        }
+    }
+
+    #[test]
+    fn test_per_task_vars() {
+        let loaders: Vec<Box<dyn ConfigLoaderTrait>> = vec![Box::new(FlowFactsLoader)];
+        let cmd = ["otawa", "$tacle_exec", "$flowfacts"].map(String::from);
+        let tasks = tacle_loader(&["kernel"]).fill(&cmd, &loaders).unwrap();
+        let fft = tasks.iter().find(|t| t.name == "fft").unwrap();
+        assert_eq!(fft.args[1], "ff/fft.ff");
+        assert!(tasks
+            .iter()
+            .all(|t| t.args[1] == format!("ff/{}.ff", t.name)));
+    }
+
+    #[test]
+    fn test_include() {
+        let dir = std::env::temp_dir().join("zexp-test-include");
+        std::fs::create_dir_all(dir.join("common")).unwrap();
+        std::fs::write(
+            dir.join("common").join("base.toml"),
+            "CMD = \"tool $x\"\n[TASK]\nname = \"base\"\ntimeout = 10\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("common").join("env.toml"),
+            "[ENV]\nLANG = \"C\"\n[TASK]\nretries = 2\n",
+        )
+        .unwrap();
+        let main = dir.join("main.toml");
+        write_script(
+            &main,
+            "include = [\"common/base.toml\", \"common/env.toml\"]\n\
+             [TASK]\nname = \"main\"\n",
+        );
+        let script = Script::from_file(main.to_str().unwrap()).unwrap();
+        let config = &script.script_config;
+        assert!(!config.contains_key("include"));
+        assert_eq!(config["CMD"].as_str(), Some("tool $x"));
+        assert_eq!(config["ENV"]["LANG"].as_str(), Some("C"));
+        // the tables are merged, the main script wins
+        assert_eq!(config["TASK"]["name"].as_str(), Some("main"));
+        assert_eq!(config["TASK"]["timeout"].as_integer(), Some(10));
+        assert_eq!(config["TASK"]["retries"].as_integer(), Some(2));
+
+        std::fs::write(dir.join("a.toml"), "include = [\"b.toml\"]\n").unwrap();
+        std::fs::write(dir.join("b.toml"), "include = [\"a.toml\"]\n").unwrap();
+        write_script(&main, "include = [\"a.toml\"]\nCMD = \"true\"\n");
+        let err = Script::from_file(main.to_str().unwrap()).err().unwrap();
+        assert!(matches!(err, ScriptError::InvalidInclude(_)));
+        assert!(err.to_string().contains("a.toml -> "));
+    }
+
+    #[test]
+    fn test_env_interpolation() {
+        let path = std::env::temp_dir().join("zexp-test-env-interpolation.toml");
+        write_script(
+            &path,
+            "CMD = \"tool ${env:PATH} $$x\"\n[TASK]\nworking_dir = \"${env:PATH}\"\n",
+        );
+        let task = &load_script(path.to_str().unwrap())
+            .unwrap()
+            .gen_cmd()
+            .unwrap()[0];
+        let env_path = std::env::var("PATH").unwrap();
+        assert_eq!(task.args, [env_path.as_str(), "$x"]);
+        assert_eq!(task.cwd, Some(PathBuf::from(&env_path)));
+
+        write_script(&path, "CMD = \"${env:ZEXP_TEST_UNSET}/tool\"\n");
+        let err = load_script(path.to_str().unwrap()).err().unwrap();
+        assert!(matches!(err, ScriptError::UnsetEnv(_)));
+        assert!(err.to_string().contains("ZEXP_TEST_UNSET is not set"));
+    }
+
+    #[test]
+    fn test_reserved_vars() {
+        let cmd = ["tool", "$tacle_exec", "--out=$benchset/$name.xml"].map(String::from);
+        let tasks = tacle_loader(&["kernel"]).fill(&cmd, &[]).unwrap();
+        let fft = tasks.iter().find(|t| t.name == "fft").unwrap();
+        assert_eq!(fft.args[1], "--out=kernel/fft.xml");
+
+        let path = std::env::temp_dir().join("zexp-test-reserved-vars.toml");
+        write_script(
+            &path,
+            "CMD = [\"tool $name\", { label = \"b\", cmd = \"tool ${name}.xml\" }]\n\
+             [GRID]\n\
+             \"$x\" = [1]\n",
+        );
+        let tasks = load_script(path.to_str().unwrap())
+            .unwrap()
+            .gen_cmd()
+            .unwrap();
+        let args: Vec<&str> = tasks.iter().map(|t| t.args[0].as_str()).collect();
+        assert_eq!(args, ["x-1__0", "x-1__b.xml"]);
+        // $benchset is only filled for the tasks of a benchset
+        write_script(&path, "CMD = \"tool $benchset\"\n[GRID]\n\"$x\" = [1]\n");
+        let err = load_script(path.to_str().unwrap())
+            .unwrap()
+            .gen_cmd()
+            .unwrap_err();
+        assert!(err.contains("unresolved variables: $benchset"));
+        write_script(&path, "CMD = \"tool $name\"\n[GRID]\n\"$name\" = [1]\n");
+        let err = load_script(path.to_str().unwrap())
+            .unwrap()
+            .gen_cmd()
+            .unwrap_err();
+        assert!(err.contains("$name is reserved"));
+    }
+
+    /// loaders providing the same variable, with different values
+    struct FirstLoader;
+    struct SecondLoader;
+
+    impl LoadableFromConfig for FirstLoader {
+        fn from(_config: toml::Table) -> Result<Self, ScriptError> {
+            Ok(FirstLoader)
+        }
+    }
+
+    impl LoadableFromConfig for SecondLoader {
+        fn from(_config: toml::Table) -> Result<Self, ScriptError> {
+            Ok(SecondLoader)
+        }
+    }
+
+    impl ConfigLoaderTrait for FirstLoader {
+        fn provided_vars(&self) -> Vec<String> {
+            vec!["$foo".to_string()]
+        }
+
+        fn get_terms(&self, _var_name: &str) -> Result<Vec<String>, String> {
+            Ok(vec!["first".to_string()])
+        }
+    }
+
+    impl ConfigLoaderTrait for SecondLoader {
+        fn provided_vars(&self) -> Vec<String> {
+            vec!["$foo".to_string(), "$bar".to_string()]
+        }
+
+        fn get_terms(&self, _var_name: &str) -> Result<Vec<String>, String> {
+            Ok(vec!["second".to_string()])
+        }
+    }
+
+    #[test]
+    fn test_duplicate_provider() {
+        let path = std::env::temp_dir().join("zexp-test-duplicate-provider.toml");
+        write_script(&path, "CMD = \"tool $foo $bar\"");
+        let mut script = Script::from_file(path.to_str().unwrap()).unwrap();
+        script.register_loader::<FirstLoader>().unwrap();
+        script.register_loader::<SecondLoader>().unwrap();
+        // the first registered loader wins, without concatenating the terms of the others
+        assert_eq!(
+            script.fill_static_vars().unwrap()[0].terms,
+            ["tool", "first", "second"]
+        );
+    }
+
+    /// a loader giving the value of each of its variables
+    struct ValuesLoader(Vec<(&'static str, &'static str)>);
+
+    impl ConfigLoaderTrait for ValuesLoader {
+        fn provided_vars(&self) -> Vec<String> {
+            self.0.iter().map(|(var, _)| var.to_string()).collect()
+        }
+
+        fn get_terms(&self, var_name: &str) -> Result<Vec<String>, String> {
+            let (_, value) = self.0.iter().find(|(var, _)| *var == var_name).unwrap();
+            Ok(vec![value.to_string()])
+        }
+    }
+
+    #[test]
+    fn test_nested_vars() {
+        let path = std::env::temp_dir().join("zexp-test-nested-vars.toml");
+        write_script(&path, "CMD = \"tool $opts $x\"");
+        let mut script = Script::from_file(path.to_str().unwrap()).unwrap();
+        script.loaders.push(Box::new(ValuesLoader(vec![
+            ("$opts", "--mode=${mode}_$unknown"),
+            ("$mode", "$level"),
+            ("$level", "fast"),
+            ("$x", "pre_$y"),
+            ("$y", "$z"),
+            ("$z", "$x"),
+        ])));
+        assert_eq!(
+            script.fill_static_terms(&["$opts"]).unwrap(),
+            ["--mode=fast_$unknown"]
+        );
+        let err = script.fill_static_vars().err().unwrap();
+        assert!(err.contains("$x -> $y -> $z -> $x"), "{}", err);
+        let err = script.fill_static_terms(&["$y"]).unwrap_err();
+        assert!(err.contains("$y -> $z -> $x -> $y"), "{}", err);
+    }
+
+    #[test]
+    fn test_inline_vars() {
+        let path = std::env::temp_dir().join("zexp-test-inline-vars.toml");
+        write_script(
+            &path,
+            "CMD = \"tool --out=$mode.xml prefix_${threshold}_x cost$$mode\"\n\
+             [GRID]\n\
+             \"$threshold\" = [1]\n\
+             \"$mode\" = [\"a\"]\n",
+        );
+        let tasks = load_script(path.to_str().unwrap())
+            .unwrap()
+            .gen_cmd()
+            .unwrap();
+        assert_eq!(tasks[0].args, ["--out=a.xml", "prefix_1_x", "cost$mode"]);
+    }
+
+    fn otawa_loader(options: &str) -> OTAWAConfigLoader {
+        let props = if options.contains("props") {
+            ""
+        } else {
+            "props = [\"otawa::A\"]\n"
+        };
+        let config = format!(
+            "[OTAWA]\nPROVIDED_VARS = [\"$otawa_opts\"]\napp_path = \"otawa\"\nno_app_check = true\n{}{}",
+            props, options
+        );
+        <OTAWAConfigLoader as LoadableFromConfig>::from(config.parse().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_otawa_app_path() {
+        let path = std::env::temp_dir().join("zexp-test-otawa-app-path.toml");
+        let load = |app_path: &str| {
+            write_script(
+                &path,
+                &format!(
+                    "CMD = \"$otawa_app\"\nloaders = [\"OTAWA\"]\n[OTAWA]\n\
+                     PROVIDED_VARS = [\"$otawa_app\"]\nprops = []\napp_path = \"{}\"\n",
+                    app_path
+                ),
+            );
+            load_script(path.to_str().unwrap())
+        };
+        let err = load("").err().unwrap();
+        assert!(matches!(err, ScriptError::InvalidTable(ref table, _) if table == "OTAWA"));
+        assert!(err.to_string().contains("app_path is empty"));
+        let err = load("/nonexistent/otawa").err().unwrap();
+        assert!(err
+            .to_string()
+            .contains("/nonexistent/otawa does not exist"));
+        let tasks = load("/bin/sh").unwrap().gen_cmd().unwrap();
+        assert_eq!(tasks[0].cmd, "/bin/sh");
+    }
+
+    #[test]
+    fn test_empty_command() {
+        let path = std::env::temp_dir().join("zexp-test-empty-command.toml");
+        write_script(
+            &path,
+            "CMD = \"$otawa_opts\"\nloaders = [\"OTAWA\"]\n[OTAWA]\n\
+             PROVIDED_VARS = [\"$otawa_opts\"]\napp_path = \"otawa\"\nno_app_check = true\n\
+             props = []\n",
+        );
+        let err = load_script(path.to_str().unwrap())
+            .unwrap()
+            .gen_cmd()
+            .unwrap_err();
+        assert_eq!(err, "the command is empty");
+    }
+
+    #[test]
+    fn test_invalid_loader_table() {
+        let path = std::env::temp_dir().join("zexp-test-invalid-loader-table.toml");
+        let load = |content: &str| {
+            write_script(&path, content);
+            load_script(path.to_str().unwrap()).err().unwrap()
+        };
+        let err = load("CMD = \"$otawa_app\"\nloaders = [\"OTAWA\"]\n[OTAWA]\nprops = []\n");
+        assert!(matches!(err, ScriptError::InvalidTable(ref table, _) if table == "OTAWA"));
+        assert!(err.to_string().contains("missing field `PROVIDED_VARS`"));
+        let err = load("CMD = \"tool\"\n[GRID]\nretries = \"x\"\n");
+        assert!(matches!(err, ScriptError::InvalidTable(ref table, _) if table == "GRID"));
+        assert!(
+            err.to_string().ends_with("expected u32 in `retries`"),
+            "{}",
+            err
+        );
+        let err = load("CMD = \"tool\"\nloaders = [\"OTAWA\"]\n");
+        assert!(err
+            .to_string()
+            .contains("invalid OTAWA table in script: the script has no [OTAWA] table"));
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("90"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("90s"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("30m"), Ok(Duration::from_secs(30 * 60)));
+        assert_eq!(parse_duration("2h"), Ok(Duration::from_secs(2 * 3600)));
+        assert_eq!(parse_duration("1d"), Ok(Duration::from_secs(86400)));
+        assert!(parse_duration("h").is_err());
+        assert!(parse_duration("6x").is_err());
+        assert!(parse_duration("1h30m").is_err());
+        assert!(parse_duration("999999999999999999d")
+            .unwrap_err()
+            .contains("too large"));
+    }
+
+    #[test]
+    fn test_human_timeout() {
+        let path = std::env::temp_dir().join("zexp-test-human-timeout.toml");
+        let load = |timeout: &str| {
+            write_script(
+                &path,
+                &format!(
+                    "CMD = \"tool $x\"\n[GRID]\ntimeout = {}\n\"$x\" = [1]\n",
+                    timeout
+                ),
+            );
+            load_script(path.to_str().unwrap())
+        };
+        let timeout = |timeout: &str| load(timeout).unwrap().gen_cmd().unwrap()[0].timeout_secs;
+        assert_eq!(timeout("90"), Some(90));
+        assert_eq!(timeout("\"90s\""), Some(90));
+        assert_eq!(timeout("\"30m\""), Some(1800));
+        assert_eq!(timeout("\"2h\""), Some(7200));
+        let err = load("\"2 hours\"").err().unwrap();
+        assert!(err.to_string().contains("unknown unit"), "{}", err);
+    }
+
+    #[test]
+    fn test_tacle_desc_csv() {
+        let dir = std::env::temp_dir().join("zexp-test-tacle-desc-csv");
+        std::fs::create_dir_all(&dir).unwrap();
+        let csv = dir.join("benchs.csv");
+        std::fs::write(
+            &csv,
+            "name,exec,entry_point,benchset\nfft,fft.elf,,kernel\nmd5,md5.elf,md5_main,kernel\n",
+        )
+        .unwrap();
+        let mut script = load_script(&example_script("zexp-test-tacle-desc-csv.toml")).unwrap();
+        script.set_tacle_desc_csv(csv.to_str().unwrap()).unwrap();
+        script.register_loaders(&LoaderRegistry::default()).unwrap();
+        // the CSV replaces the tacle_desc_path of the script
+        let names: Vec<String> = script
+            .gen_cmd()
+            .unwrap()
+            .into_iter()
+            .map(|task| task.name)
+            .collect();
+        assert_eq!(names, ["fft", "md5"]);
+
+        // there is no TACLE table to read the benchs into
+        let path = std::env::temp_dir().join("zexp-test-tacle-desc-csv-task.toml");
+        write_script(&path, "CMD = \"true\"");
+        let mut script = load_script(path.to_str().unwrap()).unwrap();
+        assert!(script.set_tacle_desc_csv(csv.to_str().unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_effective_config() {
+        let dir = std::env::temp_dir().join("zexp-test-effective-config");
+        std::fs::create_dir_all(&dir).unwrap();
+        write_script(&dir.join("grid.toml"), "[GRID]\n\"$x\" = [1, 2]\n");
+        let path = dir.join("script.toml");
+        write_script(&path, "include = [\"grid.toml\"]\nCMD = \"tool $x\"\n");
+        let config = Script::from_file(path.to_str().unwrap())
+            .unwrap()
+            .effective_config()
+            .unwrap();
+        assert_eq!(config["main_loader"].as_str(), Some("GRID"));
+        assert_eq!(config["loaders"], toml::Value::Array(Vec::new()));
+        assert_eq!(
+            config["GRID"]["$x"],
+            toml::Value::Array(vec![1.into(), 2.into()])
+        );
+        assert!(!config.contains_key("include"));
+        // it is a script that loads the same
+        let printed = dir.join("printed.toml");
+        std::fs::write(&printed, toml::to_string(&config).unwrap()).unwrap();
+        let tasks = load_script(printed.to_str().unwrap())
+            .unwrap()
+            .gen_cmd()
+            .unwrap();
+        assert_eq!(tasks.len(), 2);
+    }
+
+    #[test]
+    fn test_loader_registry() {
+        let path = std::env::temp_dir().join("zexp-test-loader-registry.toml");
+        write_script(
+            &path,
+            "main_loader = \"GRID\"\nCMD = \"tool $x\"\n[GRID]\n\"$x\" = [1, 2]\n",
+        );
+        let tasks = load_script(path.to_str().unwrap())
+            .unwrap()
+            .gen_cmd()
+            .unwrap();
+        assert_eq!(tasks.len(), 2);
+
+        write_script(&path, "main_loader = \"NOPE\"\nCMD = \"true\"\n");
+        let err = load_script(path.to_str().unwrap()).err().unwrap();
+        assert!(matches!(err, ScriptError::InvalidLoader(_)));
+        assert!(err.to_string().contains("GRID, INPUTS, TACLE, TASK"));
+
+        write_script(&path, "loaders = \"OTAWA\"\nCMD = \"true\"\n");
+        let err = load_script(path.to_str().unwrap()).err().unwrap();
+        assert!(matches!(err, ScriptError::InvalidLoader(_)));
+
+        // a registry with the loaders of the caller only
+        write_script(&path, "main_loader = \"ONCE\"\nCMD = \"true\"\n");
+        let mut registry = LoaderRegistry::empty();
+        registry.add_main_loader::<SingleTaskLoader>("ONCE");
+        let mut script = Script::from_file(path.to_str().unwrap()).unwrap();
+        script.register_loaders(&registry).unwrap();
+        assert_eq!(script.gen_cmd().unwrap()[0].name, "true");
+        let mut script = Script::from_file(path.to_str().unwrap()).unwrap();
+        assert!(script.register_loaders(&LoaderRegistry::default()).is_err());
+    }
+
+    #[test]
+    fn test_from_reader() {
+        let toml = "zexp_version = 1\nCMD = \"tool $mode\"\n[GRID]\n\"$mode\" = [\"a\", \"b\"]\n";
+        let tasks = load_script_from_reader(io::Cursor::new(toml), "<stdin>")
+            .unwrap()
+            .gen_cmd()
+            .unwrap();
+        assert_eq!(tasks.len(), 2);
+        assert_eq!(tasks[0].cmd, "tool");
+
+        let err = Script::from_reader(io::Cursor::new("CMD = "), "<stdin>")
+            .err()
+            .unwrap();
+        assert!(matches!(err, ScriptError::Parse(..)));
+        assert!(err.to_string().contains("<stdin>"));
+    }
+
+    #[test]
+    fn test_single_task() {
+        let path = std::env::temp_dir().join("zexp-test-single-task.toml");
+        write_script(&path, "CMD = \"/bin/echo 'hello world'\"");
+        let tasks = load_script(path.to_str().unwrap())
+            .unwrap()
+            .gen_cmd()
+            .unwrap();
+        assert_eq!(tasks.len(), 1);
+        assert_eq!(tasks[0].name, "echo");
+        assert_eq!(tasks[0].args, ["hello world"]);
+
+        write_script(
+            &path,
+            "CMD = \"sleep 10\"\n[TASK]\nname = \"nap\"\ntimeout = 1",
+        );
+        let tasks = single_task_script(Script::from_file(path.to_str().unwrap()).unwrap())
+            .unwrap()
+            .gen_cmd()
+            .unwrap();
+        assert_eq!(tasks[0].name, "nap");
+        assert_eq!(tasks[0].timeout_secs, Some(1));
+
+        write_script(&path, "CMD = \"echo $unknown\"");
+        let err = single_task_script(Script::from_file(path.to_str().unwrap()).unwrap())
+            .unwrap()
+            .gen_cmd()
+            .unwrap_err();
+        assert!(err.contains("$unknown"), "{}", err);
+    }
+
+    #[test]
+    fn test_otawa_log() {
+        let opts = |options| otawa_loader(options).get_terms("$otawa_opts").unwrap();
+        assert_eq!(
+            opts("log_level = \"bb\""),
+            ["--add-prop", "otawa::A", "--log", "bb"]
+        );
+        assert_eq!(opts("log_level = \"\""), ["--add-prop", "otawa::A"]);
+        assert_eq!(opts(""), ["--add-prop", "otawa::A"]);
+        assert_eq!(
+            opts("log_level = \"bb\"\nno_log = true"),
+            ["--add-prop", "otawa::A"]
+        );
+    }
+
+    #[test]
+    fn test_otawa_props() {
+        let opts = |options| otawa_loader(options).get_terms("$otawa_opts").unwrap();
+        assert_eq!(
+            opts("props = [\"otawa::A\", \"otawa::B=true\"]"),
+            ["--add-prop", "otawa::A", "--add-prop", "otawa::B=true"]
+        );
+        assert_eq!(
+            opts("[OTAWA.props]\n\"otawa::B\" = true\n\"otawa::PATH\" = \"/a b\""),
+            [
+                "--add-prop",
+                "otawa::B=true",
+                "--add-prop",
+                "otawa::PATH=/a b"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_otawa_loader() {
+        use simplelog::*;
+        TermLogger::init(
+            LevelFilter::Trace,
+            Config::default(),
+            TerminalMode::Mixed,
+            ColorChoice::Auto,
+        )
+        .unwrap();
+        let mut script = otawa_tacle_script(
+            Script::from_file(&example_script("zexp-test-otawa-loader.toml")).unwrap(),
         )
         .unwrap();
-        let mut script =
-            otawa_tacle_script("/home/acac/rust-zexp/scripts/otawa-tacle-exp/example.toml");
         let cmds = script.gen_cmd().unwrap();
         debug!("{:?}", cmds)
     }
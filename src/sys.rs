@@ -0,0 +1,134 @@
+//! The platform-specific settings of the tasks, applied in the child before it runs the task,
+//! with a warning where they are not supported, the measures of the running tasks,
+//! and how they terminated
+use std::process::{Command, ExitStatus};
+
+/// limit the address space of the child to mem_limit_mb MB
+#[cfg(target_os = "linux")]
+pub fn set_mem_limit(command: &mut Command, mem_limit_mb: u64) {
+    use std::os::unix::process::CommandExt;
+    let bytes = mem_limit_mb.saturating_mul(1024 * 1024);
+    // SAFETY: the hook only calls setrlimit, which is async-signal-safe and does not allocate
+    unsafe {
+        command.pre_exec(move || rlimit::setrlimit(rlimit::Resource::AS, bytes, bytes));
+    }
+}
+
+/// memory limits are only supported on Linux
+#[cfg(not(target_os = "linux"))]
+pub fn set_mem_limit(_command: &mut Command, mem_limit_mb: u64) {
+    log::warn!(
+        "Memory limit of {} MB ignored, only supported on Linux",
+        mem_limit_mb
+    );
+}
+
+/// set the niceness of the child to nice, from -20 (highest priority) to 19 (lowest)
+#[cfg(unix)]
+pub fn set_nice(command: &mut Command, nice: i32) {
+    use std::os::unix::process::CommandExt;
+    // SAFETY: the hook only calls setpriority, which is async-signal-safe and does not allocate
+    unsafe {
+        command.pre_exec(move || {
+            // the type of the `which` parameter differs between the libc of the platforms
+            #[allow(clippy::useless_conversion)]
+            if libc::setpriority(libc::PRIO_PROCESS.try_into().unwrap_or_default(), 0, nice) == 0 {
+                Ok(())
+            } else {
+                Err(std::io::Error::last_os_error())
+            }
+        });
+    }
+}
+
+/// niceness is only supported on Unix
+#[cfg(not(unix))]
+pub fn set_nice(_command: &mut Command, nice: i32) {
+    log::warn!("Niceness {} ignored, only supported on Unix", nice);
+}
+
+/// run the child on core only
+#[cfg(target_os = "linux")]
+pub fn set_cpu_affinity(command: &mut Command, core: usize) {
+    use std::os::unix::process::CommandExt;
+    // SAFETY: the hook only calls sched_setaffinity, which is async-signal-safe, on a set
+    // built on the stack
+    unsafe {
+        command.pre_exec(move || {
+            let mut set: libc::cpu_set_t = std::mem::zeroed();
+            libc::CPU_SET(core, &mut set);
+            if libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) == 0 {
+                Ok(())
+            } else {
+                Err(std::io::Error::last_os_error())
+            }
+        });
+    }
+}
+
+/// CPU affinity is only supported on Linux
+#[cfg(not(target_os = "linux"))]
+pub fn set_cpu_affinity(_command: &mut Command, core: usize) {
+    log::warn!("Affinity to core {} ignored, only supported on Linux", core);
+}
+
+/// the peak resident memory in kB of the running process pid so far, its `VmHWM` in
+/// `/proc/<pid>/status`, `None` if it cannot be read, e.g. once the process is reaped
+#[cfg(target_os = "linux")]
+pub fn peak_rss_kb(pid: u32) -> Option<u64> {
+    let status = std::fs::read_to_string(format!("/proc/{}/status", pid)).ok()?;
+    let hwm = status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmHWM:"))?;
+    hwm.trim().strip_suffix("kB")?.trim().parse().ok()
+}
+
+/// the peak resident memory is only measured on Linux
+#[cfg(not(target_os = "linux"))]
+pub fn peak_rss_kb(_pid: u32) -> Option<u64> {
+    None
+}
+
+/// the signal that terminated the process of status, `None` if it exited by itself
+#[cfg(unix)]
+pub fn exit_signal(status: ExitStatus) -> Option<i32> {
+    use std::os::unix::process::ExitStatusExt;
+    status.signal()
+}
+
+/// the processes are only terminated by signals on Unix
+#[cfg(not(unix))]
+pub fn exit_signal(_status: ExitStatus) -> Option<i32> {
+    None
+}
+
+/// the name of the common signal sig, e.g. `SIGSEGV`, their numbers differ between platforms
+#[cfg(unix)]
+pub fn signal_name(sig: i32) -> Option<&'static str> {
+    Some(match sig {
+        libc::SIGHUP => "SIGHUP",
+        libc::SIGINT => "SIGINT",
+        libc::SIGQUIT => "SIGQUIT",
+        libc::SIGILL => "SIGILL",
+        libc::SIGTRAP => "SIGTRAP",
+        libc::SIGABRT => "SIGABRT",
+        libc::SIGBUS => "SIGBUS",
+        libc::SIGFPE => "SIGFPE",
+        libc::SIGKILL => "SIGKILL",
+        libc::SIGUSR1 => "SIGUSR1",
+        libc::SIGSEGV => "SIGSEGV",
+        libc::SIGUSR2 => "SIGUSR2",
+        libc::SIGPIPE => "SIGPIPE",
+        libc::SIGALRM => "SIGALRM",
+        libc::SIGTERM => "SIGTERM",
+        libc::SIGXCPU => "SIGXCPU",
+        libc::SIGXFSZ => "SIGXFSZ",
+        _ => return None,
+    })
+}
+
+/// the signals are only named on Unix
+#[cfg(not(unix))]
+pub fn signal_name(_sig: i32) -> Option<&'static str> {
+    None
+}
@@ -1,72 +1,364 @@
+use crate::vars;
 use serde::Deserialize;
+use std::fmt;
+use std::fs;
 use std::fs::File;
+use std::io;
 use std::io::prelude::*;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use toml::Table;
 
+/// Errors when loading a TACLe description, each one carries the path of the description
+#[derive(Debug)]
+pub enum TacleError {
+    Open(String, io::Error),
+    Read(String, io::Error),
+    Parse(String, toml::de::Error),
+    Deserialize(String, toml::de::Error),
+    /// a `${env:NAME}` refers to an environment variable that is not set
+    UnsetEnv(String, String),
+    /// a CSV description is malformed, with the line number and the problem
+    Csv(String, String),
+}
+
+impl fmt::Display for TacleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TacleError::Open(path, e) => {
+                write!(f, "failed to open TACLe description {}: {}", path, e)
+            }
+            TacleError::Read(path, e) => {
+                write!(f, "failed to read TACLe description {}: {}", path, e)
+            }
+            TacleError::Parse(path, e) => {
+                write!(f, "TOML syntax error in TACLe description {}: {}", path, e)
+            }
+            TacleError::Deserialize(path, e) => {
+                write!(f, "invalid TACLe description {}: {}", path, e)
+            }
+            TacleError::UnsetEnv(path, e) => write!(f, "in TACLe description {}: {}", path, e),
+            TacleError::Csv(path, e) => write!(f, "invalid TACLe CSV {}: {}", path, e),
+        }
+    }
+}
+
+impl std::error::Error for TacleError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TacleError::Open(_, e) | TacleError::Read(_, e) => Some(e),
+            TacleError::Parse(_, e) | TacleError::Deserialize(_, e) => Some(e),
+            TacleError::UnsetEnv(_, _) | TacleError::Csv(_, _) => None,
+        }
+    }
+}
+
+/// the entry point of the benchs that do not give one
+fn default_entry_point() -> String {
+    "main".to_string()
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct Bench {
     pub name: String,
     pub exec: String,
+    /// "main" if not given
+    #[serde(default = "default_entry_point")]
     pub entry_point: String,
+    /// name of the benchset the bench comes from, set when loading the description
+    #[serde(skip)]
+    pub benchset: String,
+    /// the terms appended to the command of the bench, the `cmd_extra` of its benchset
+    #[serde(skip)]
+    pub cmd_extra: Vec<String>,
+    /// what the bench is about, the description of its benchset if it has none
+    pub description: Option<String>,
+    /// estimated cost of the bench, e.g. its duration in seconds, see `Task::weight`
+    pub weight: Option<f64>,
 }
 
 #[derive(Deserialize)]
 pub struct BenchSet {
     name: String,
     path_from_root: PathBuf,
+    /// terms appended to the command of the benchs of this benchset only
+    #[serde(default)]
+    cmd_extra: Vec<String>,
+    /// what the benchset is about
+    description: Option<String>,
     benchs: Vec<Bench>,
 }
 
+/// name of the benchset of the benchs given at the top level of the description
+pub const DEFAULT_BENCHSET: &str = "default";
+
+/// A TACLe description, its benchs are given in benchsets, or directly in a flat list
+/// (or both) for the benchs that do not belong to any benchset
 #[derive(Deserialize)]
 pub struct TACLe {
     root_path: String,
+    #[serde(default)]
     benchsets: Vec<BenchSet>,
+    /// benchs in the implicit `DEFAULT_BENCHSET` benchset, their exec is relative to root_path
+    #[serde(default)]
+    benchs: Vec<Bench>,
 }
 
 impl TACLe {
-    pub fn from_script(script_path: &str) -> TACLe {
-        let mut file = File::open(script_path)
-            .expect(format!("failed to open script file {}", script_path).as_str());
+    pub fn from_script(script_path: &str) -> Result<TACLe, TacleError> {
+        let mut file =
+            File::open(script_path).map_err(|e| TacleError::Open(script_path.to_string(), e))?;
 
         let mut script_content = String::new();
         file.read_to_string(&mut script_content)
-            .expect("Error when reading script file");
+            .map_err(|e| TacleError::Read(script_path.to_string(), e))?;
 
-        let script_content = script_content
+        let mut script_content = script_content
             .parse::<Table>()
-            .expect("Error when parsing the script file, check you TOML syntax!");
+            .map_err(|e| TacleError::Parse(script_path.to_string(), e))?;
+        // the inline descriptions are interpolated with the rest of their script
+        vars::interpolate_env_table(&mut script_content)
+            .map_err(|e| TacleError::UnsetEnv(script_path.to_string(), e))?;
+        // a relative root_path is relative to the description, not to where zexp is run
+        let desc_dir = Path::new(script_path).parent().unwrap_or(Path::new(""));
+        Self::from_named_table(script_path, script_content, desc_dir)
+    }
 
-        let mut res: TACLe = script_content.try_into().unwrap();
-        res.patch_full_exec_name();
-        res
+    /// the description given by a table of a script, e.g. `[TACLE.inline]`, instead of a file,
+    /// a relative root_path is relative to the current directory, `Script` makes the one of
+    /// `[TACLE.inline]` relative to the script
+    pub fn from_table(table: Table) -> Result<TACLe, TacleError> {
+        Self::from_named_table("TACLE.inline", table, Path::new(""))
+    }
+
+    /// the description given by a CSV file with a header line naming its columns: `name` and
+    /// `exec` are required, `entry_point`, `benchset`, `description` and `weight` are optional,
+    /// the other columns are ignored, a relative exec is relative to the CSV file
+    pub fn from_csv(csv_path: &str) -> Result<TACLe, TacleError> {
+        let content =
+            fs::read_to_string(csv_path).map_err(|e| TacleError::Read(csv_path.to_string(), e))?;
+        let csv_error = |line: usize, e: String| {
+            TacleError::Csv(csv_path.to_string(), format!("line {}: {}", line, e))
+        };
+        // spreadsheets may start their exports with a byte order mark
+        let content = content.strip_prefix('\u{feff}').unwrap_or(&content);
+        let mut lines = content
+            .lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line))
+            .filter(|(_, line)| !line.trim().is_empty());
+        let (header_line, header) = lines
+            .next()
+            .ok_or_else(|| TacleError::Csv(csv_path.to_string(), "no header line".to_string()))?;
+        let header = split_csv_record(header).map_err(|e| csv_error(header_line, e))?;
+        let column = |name: &str| header.iter().position(|column| column.trim() == name);
+        let (Some(name_column), Some(exec_column)) = (column("name"), column("exec")) else {
+            return Err(csv_error(
+                header_line,
+                "the header must have a name and an exec column".to_string(),
+            ));
+        };
+        let entry_point_column = column("entry_point");
+        let benchset_column = column("benchset");
+        let description_column = column("description");
+        let weight_column = column("weight");
+
+        let mut benchsets: Vec<BenchSet> = Vec::new();
+        for (line, record) in lines {
+            let record = split_csv_record(record).map_err(|e| csv_error(line, e))?;
+            // a missing trailing field is an empty one
+            let field = |column: Option<usize>| {
+                column
+                    .and_then(|column| record.get(column))
+                    .map(|field| field.trim())
+                    .filter(|field| !field.is_empty())
+            };
+            let (Some(name), Some(exec)) = (field(Some(name_column)), field(Some(exec_column)))
+            else {
+                return Err(csv_error(
+                    line,
+                    "a bench needs a name and an exec".to_string(),
+                ));
+            };
+            let weight =
+                match field(weight_column) {
+                    Some(weight) => Some(weight.parse::<f64>().map_err(|e| {
+                        csv_error(line, format!("invalid weight {:?}: {}", weight, e))
+                    })?),
+                    None => None,
+                };
+            let bench = Bench {
+                name: name.to_string(),
+                exec: exec.to_string(),
+                entry_point: field(entry_point_column)
+                    .map(str::to_string)
+                    .unwrap_or_else(default_entry_point),
+                benchset: String::new(),
+                cmd_extra: Vec::new(),
+                description: field(description_column).map(str::to_string),
+                weight,
+            };
+            let benchset_name = field(benchset_column).unwrap_or(DEFAULT_BENCHSET);
+            match benchsets.iter_mut().find(|x| x.name == benchset_name) {
+                Some(benchset) => benchset.benchs.push(bench),
+                None => benchsets.push(BenchSet {
+                    name: benchset_name.to_string(),
+                    path_from_root: PathBuf::new(),
+                    cmd_extra: Vec::new(),
+                    description: None,
+                    benchs: vec![bench],
+                }),
+            }
+        }
+        let mut res = TACLe {
+            root_path: String::new(),
+            benchsets,
+            benchs: Vec::new(),
+        };
+        let csv_dir = Path::new(csv_path).parent().unwrap_or(Path::new(""));
+        res.patch_full_exec_name(csv_dir);
+        Ok(res)
+    }
+
+    /// the description given by table, name is the one given in the errors,
+    /// and base_dir the directory a relative root_path is relative to
+    fn from_named_table(name: &str, table: Table, base_dir: &Path) -> Result<TACLe, TacleError> {
+        let mut res: TACLe = table
+            .try_into()
+            .map_err(|e| TacleError::Deserialize(name.to_string(), e))?;
+        if !res.benchs.is_empty() {
+            res.benchsets.push(BenchSet {
+                name: DEFAULT_BENCHSET.to_string(),
+                path_from_root: PathBuf::new(),
+                cmd_extra: Vec::new(),
+                description: None,
+                benchs: std::mem::take(&mut res.benchs),
+            });
+        }
+        res.patch_full_exec_name(base_dir);
+        Ok(res)
     }
 
     /// the exec of each bench is only the path from the benchset root, so patch it to have absolute path
-    fn patch_full_exec_name(&mut self) {
+    /// and tag each bench with its benchset and the extra terms of its command
+    /// an absolute exec is kept as is, a relative root_path is relative to base_dir,
+    /// and the path is canonicalized if the exec exists
+    fn patch_full_exec_name(&mut self, base_dir: &Path) {
         for benchset in self.benchsets.iter_mut() {
             for bench in benchset.benchs.iter_mut() {
-                let full_exec_name = PathBuf::from(&self.root_path)
+                bench.benchset = benchset.name.clone();
+                bench.cmd_extra = benchset.cmd_extra.clone();
+                if bench.description.is_none() {
+                    bench.description = benchset.description.clone();
+                }
+                let full_exec_name = base_dir
+                    .join(&self.root_path)
                     .join(&benchset.path_from_root)
                     .join(&bench.exec);
-                bench.exec = full_exec_name.to_str().unwrap().to_string();
+                let full_exec_name = fs::canonicalize(&full_exec_name)
+                    .or_else(|_| std::path::absolute(&full_exec_name))
+                    .unwrap_or(full_exec_name);
+                bench.exec = full_exec_name.to_string_lossy().into_owned();
             }
         }
     }
 
-    /// return a vector of benchs with respect to the benchset name given
-    pub fn select_bench(&self, benchset_name: &Vec<String>) -> Vec<Bench> {
-        let benchsets: Vec<&BenchSet> = self
-            .benchsets
-            .iter()
-            .filter(|x| benchset_name.contains(&x.name))
+    /// return the names of all the benchsets of the description
+    pub fn benchset_names(&self) -> Vec<&str> {
+        self.benchsets.iter().map(|x| x.name.as_str()).collect()
+    }
+
+    /// the benchset whose name is the closest to name, if it is close enough to be a typo
+    pub fn closest_benchset(&self, name: &str) -> Option<&str> {
+        let max_distance = (name.chars().count() / 3).max(1);
+        self.benchset_names()
+            .into_iter()
+            .map(|benchset| (levenshtein(name, benchset), benchset))
+            .filter(|(distance, _)| *distance <= max_distance)
+            .min_by_key(|(distance, _)| *distance)
+            .map(|(_, benchset)| benchset)
+    }
+
+    /// the benchset name stands for: the one named so, or else the only one it is a prefix of,
+    /// or else the closest one, see `closest_benchset`
+    pub fn match_benchset(&self, name: &str) -> Option<&str> {
+        let names = self.benchset_names();
+        if let Some(exact) = names.iter().find(|benchset| **benchset == name) {
+            return Some(exact);
+        }
+        let prefixed: Vec<&str> = names
+            .into_iter()
+            .filter(|benchset| benchset.starts_with(name))
             .collect();
-        let res: Vec<Bench> = benchsets.clone().iter().fold(Vec::new(), |mut acc, x| {
-            acc.extend(x.benchs.clone());
-            acc
-        });
-        res
+        match prefixed[..] {
+            [benchset] => Some(benchset),
+            _ => self.closest_benchset(name),
+        }
+    }
+
+    /// return the benchs whose exec does not exist on disk, with their exec
+    pub fn missing_execs<'a>(benchs: &[&'a Bench]) -> Vec<&'a Bench> {
+        benchs
+            .iter()
+            .filter(|bench| !Path::new(&bench.exec).exists())
+            .copied()
+            .collect()
+    }
+
+    /// return a vector of benchs with respect to the benchset name given,
+    /// all the benchs if no name is given, each one tagged with its benchset
+    pub fn select_bench(&self, benchset_name: &[String]) -> Vec<&Bench> {
+        self.benchsets
+            .iter()
+            .filter(|x| benchset_name.is_empty() || benchset_name.contains(&x.name))
+            .flat_map(|x| x.benchs.iter())
+            .collect()
+    }
+}
+
+/// the fields of a CSV record, separated by commas, a field may be quoted to contain commas,
+/// with its quotes doubled
+fn split_csv_record(record: &str) -> Result<Vec<String>, String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut chars = record.chars().peekable();
+    let mut quoted = false;
+    while let Some(c) = chars.next() {
+        match (quoted, c) {
+            (true, '"') if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            (true, '"') => quoted = false,
+            (false, '"') if field.trim().is_empty() => {
+                field.clear();
+                quoted = true;
+            }
+            (false, ',') => fields.push(std::mem::take(&mut field)),
+            (_, c) => field.push(c),
+        }
+    }
+    if quoted {
+        return Err("unterminated quoted field".to_string());
     }
+    fields.push(field);
+    Ok(fields)
+}
+
+/// the number of characters to insert, delete or substitute to turn a into b
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    // distances from the prefix of a read so far to each prefix of b
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != *cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(row[j + 1] + 1);
+        }
+    }
+    row[b.len()]
 }
 
 #[cfg(test)]
@@ -75,8 +367,248 @@ mod test {
     use log::debug;
     #[test]
     fn test_tacle() {
-        let script_path = "/home/acac/rust-zexp/scripts/otawa-tacle-exp/tacle.toml";
-        let tacle = TACLe::from_script(script_path);
-        debug!("{:?}", tacle.select_bench(&vec!["kernel".to_string()]));
+        let script_path = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/scripts/otawa-tacle-exp/tacle.toml"
+        );
+        let tacle = TACLe::from_script(script_path).unwrap();
+        let benchs = tacle.select_bench(&["kernel".to_string()]);
+        debug!("{:?}", benchs);
+        assert!(benchs.iter().all(|bench| bench.benchset == "kernel"));
+    }
+
+    #[test]
+    fn test_exec_paths() {
+        let dir = std::env::temp_dir().join("zexp-test-exec-paths");
+        fs::create_dir_all(dir.join("kernel")).unwrap();
+        fs::write(dir.join("kernel").join("fft.elf"), "").unwrap();
+        let desc = dir.join("tacle.toml");
+        fs::write(
+            &desc,
+            format!(
+                "root_path = {:?}\n\
+                 [[benchsets]]\nname = \"kernel\"\npath_from_root = \"kernel/../kernel\"\n\
+                 benchs = [\n\
+                 {{ name = \"fft\", exec = \"fft.elf\", entry_point = \"main\" }},\n\
+                 {{ name = \"abs\", exec = \"/opt/abs.elf\", entry_point = \"main\" }},\n\
+                 {{ name = \"missing\", exec = \"missing.elf\", entry_point = \"main\" }},\n\
+                 ]",
+                dir
+            ),
+        )
+        .unwrap();
+        let tacle = TACLe::from_script(desc.to_str().unwrap()).unwrap();
+        let benchs = tacle.select_bench(&["kernel".to_string()]);
+        let canonical_dir = fs::canonicalize(&dir).unwrap();
+        assert_eq!(
+            Path::new(&benchs[0].exec),
+            canonical_dir.join("kernel").join("fft.elf")
+        );
+        // an absolute exec replaces the root
+        assert_eq!(benchs[1].exec, "/opt/abs.elf");
+        let missing: Vec<&str> = TACLe::missing_execs(&benchs)
+            .iter()
+            .map(|bench| bench.name.as_str())
+            .collect();
+        assert_eq!(missing, ["abs", "missing"]);
+    }
+
+    #[test]
+    fn test_relative_root_path() {
+        // the tests are run from the directory of the crate, not from the one of the description
+        let dir = std::env::temp_dir().join("zexp-test-relative-root-path");
+        fs::create_dir_all(dir.join("bench").join("kernel")).unwrap();
+        fs::write(dir.join("bench").join("kernel").join("fft.elf"), "").unwrap();
+        let desc = dir.join("tacle.toml");
+        fs::write(
+            &desc,
+            "root_path = \"bench\"\n\
+             [[benchsets]]\nname = \"kernel\"\npath_from_root = \"kernel\"\n\
+             benchs = [{ name = \"fft\", exec = \"fft.elf\" }]",
+        )
+        .unwrap();
+        assert_ne!(std::env::current_dir().unwrap(), dir);
+        let tacle = TACLe::from_script(desc.to_str().unwrap()).unwrap();
+        let benchs = tacle.select_bench(&["kernel".to_string()]);
+        let canonical_dir = fs::canonicalize(&dir).unwrap();
+        assert_eq!(
+            Path::new(&benchs[0].exec),
+            canonical_dir.join("bench").join("kernel").join("fft.elf")
+        );
+        assert!(TACLe::missing_execs(&benchs).is_empty());
+    }
+
+    #[test]
+    fn test_default_entry_point() {
+        let desc = std::env::temp_dir().join("zexp-test-default-entry-point.toml");
+        fs::write(
+            &desc,
+            "root_path = \"/opt\"\n\
+             [[benchsets]]\nname = \"kernel\"\npath_from_root = \"kernel\"\n\
+             benchs = [\n\
+             { name = \"fft\", exec = \"fft.elf\" },\n\
+             { name = \"md5\", exec = \"md5.elf\", entry_point = \"md5_main\" },\n\
+             ]",
+        )
+        .unwrap();
+        let tacle = TACLe::from_script(desc.to_str().unwrap()).unwrap();
+        let benchs = tacle.select_bench(&["kernel".to_string()]);
+        assert_eq!(benchs[0].entry_point, "main");
+        assert_eq!(benchs[1].entry_point, "md5_main");
+    }
+
+    #[test]
+    fn test_select_all_benchs() {
+        let script_path = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/scripts/otawa-tacle-exp/tacle.toml"
+        );
+        let mut tacle = TACLe::from_script(script_path).unwrap();
+        let kernel = tacle.select_bench(&["kernel".to_string()]).len();
+        tacle.benchsets.push(BenchSet {
+            name: "app".to_string(),
+            path_from_root: PathBuf::from("app"),
+            cmd_extra: Vec::new(),
+            description: None,
+            benchs: vec![Bench {
+                name: "lift".to_string(),
+                exec: "lift.elf".to_string(),
+                entry_point: default_entry_point(),
+                benchset: "app".to_string(),
+                cmd_extra: Vec::new(),
+                description: None,
+                weight: None,
+            }],
+        });
+        let benchs = tacle.select_bench(&[]);
+        assert_eq!(benchs.len(), kernel + 1);
+        assert!(benchs.iter().any(|bench| bench.benchset == "kernel"));
+        assert!(benchs.iter().any(|bench| bench.benchset == "app"));
+    }
+
+    #[test]
+    fn test_flat_benchs() {
+        let desc = std::env::temp_dir().join("zexp-test-flat-benchs.toml");
+        fs::write(
+            &desc,
+            "root_path = \"/opt\"\n\
+             benchs = [\n\
+             { name = \"fft\", exec = \"fft/fft.elf\" },\n\
+             { name = \"md5\", exec = \"md5.elf\" },\n\
+             ]",
+        )
+        .unwrap();
+        let tacle = TACLe::from_script(desc.to_str().unwrap()).unwrap();
+        assert_eq!(tacle.benchset_names(), [DEFAULT_BENCHSET]);
+        let benchs = tacle.select_bench(&[]);
+        assert_eq!(benchs.len(), 2);
+        assert_eq!(benchs[0].exec, "/opt/fft/fft.elf");
+        assert!(benchs
+            .iter()
+            .all(|bench| bench.benchset == DEFAULT_BENCHSET));
+    }
+
+    #[test]
+    fn test_bench_description() {
+        let table: Table = "root_path = \"/opt\"\n\
+             [[benchsets]]\nname = \"kernel\"\npath_from_root = \"kernel\"\n\
+             description = \"small kernels\"\n\
+             benchs = [\n\
+             { name = \"fft\", exec = \"fft.elf\", description = \"Fast Fourier Transform\" },\n\
+             { name = \"md5\", exec = \"md5.elf\" },\n\
+             ]"
+        .parse()
+        .unwrap();
+        let tacle = TACLe::from_table(table).unwrap();
+        let benchs = tacle.select_bench(&[]);
+        let description = |name: &str| {
+            let bench = benchs.iter().find(|bench| bench.name == name).unwrap();
+            bench.description.as_deref()
+        };
+        assert_eq!(description("fft"), Some("Fast Fourier Transform"));
+        assert_eq!(description("md5"), Some("small kernels"));
+    }
+
+    #[test]
+    fn test_missing_description() {
+        let err = TACLe::from_script("/nonexistent/tacle.toml").err().unwrap();
+        assert!(matches!(err, TacleError::Open(..)));
+        assert!(err.to_string().contains("/nonexistent/tacle.toml"));
+    }
+
+    #[test]
+    fn test_match_benchset() {
+        assert_eq!(levenshtein("kernels", "kernel"), 1);
+        assert_eq!(levenshtein("", "app"), 3);
+        assert_eq!(levenshtein("sequentail", "sequential"), 2);
+
+        let script_path = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/scripts/otawa-tacle-exp/tacle.toml"
+        );
+        let tacle = TACLe::from_script(script_path).unwrap();
+        assert_eq!(tacle.closest_benchset("kernels"), Some("kernel"));
+        assert_eq!(tacle.closest_benchset("xyz"), None);
+        assert_eq!(tacle.match_benchset("kernel"), Some("kernel"));
+        assert_eq!(tacle.match_benchset("kern"), Some("kernel"));
+        assert_eq!(tacle.match_benchset("kernels"), Some("kernel"));
+        assert_eq!(tacle.match_benchset("xyz"), None);
+    }
+
+    #[test]
+    fn test_from_csv() {
+        let dir = std::env::temp_dir().join("zexp-test-from-csv");
+        fs::create_dir_all(&dir).unwrap();
+        let csv = dir.join("benchs.csv");
+        fs::write(
+            &csv,
+            "\u{feff}name,exec,entry_point,owner,benchset,description\r\n\
+             fft,kernel/fft.elf,,alice,kernel,\"Fast Fourier, radix 2\"\r\n\
+             \r\n\
+             md5,/opt/md5.elf,md5_main,bob,,\"the \"\"MD5\"\" hash\"\n\
+             abs,kernel/abs.elf\n",
+        )
+        .unwrap();
+        let tacle = TACLe::from_csv(csv.to_str().unwrap()).unwrap();
+        assert_eq!(tacle.benchset_names(), ["kernel", DEFAULT_BENCHSET]);
+        let benchs = tacle.select_bench(&[]);
+        let names: Vec<&str> = benchs.iter().map(|bench| bench.name.as_str()).collect();
+        assert_eq!(names, ["fft", "md5", "abs"]);
+        assert_eq!(
+            Path::new(&benchs[0].exec),
+            std::path::absolute(dir.join("kernel").join("fft.elf")).unwrap()
+        );
+        assert_eq!(benchs[0].entry_point, "main");
+        assert_eq!(
+            benchs[0].description.as_deref(),
+            Some("Fast Fourier, radix 2")
+        );
+        assert_eq!(benchs[1].exec, "/opt/md5.elf");
+        assert_eq!(benchs[1].entry_point, "md5_main");
+        assert_eq!(benchs[1].benchset, DEFAULT_BENCHSET);
+        assert_eq!(benchs[1].description.as_deref(), Some("the \"MD5\" hash"));
+        assert_eq!(benchs[1].weight, None);
+
+        fs::write(
+            &csv,
+            "name,exec,weight\nfft,fft.elf,12.5\nmd5,md5.elf,slow\n",
+        )
+        .unwrap();
+        let e = TACLe::from_csv(csv.to_str().unwrap()).err().unwrap();
+        assert!(e.to_string().contains("line 3: invalid weight"), "{}", e);
+        fs::write(&csv, "name,exec,weight\nfft,fft.elf,12.5\n").unwrap();
+        let tacle = TACLe::from_csv(csv.to_str().unwrap()).unwrap();
+        assert_eq!(tacle.select_bench(&[])[0].weight, Some(12.5));
+
+        fs::write(&csv, "name,entry_point\nfft,main\n").unwrap();
+        let e = TACLe::from_csv(csv.to_str().unwrap()).err().unwrap();
+        assert!(
+            e.to_string().contains("line 1: the header must have"),
+            "{}",
+            e
+        );
+        fs::write(&csv, "name,exec\nfft,\"fft.elf\n").unwrap();
+        let e = TACLe::from_csv(csv.to_str().unwrap()).err().unwrap();
+        assert!(e.to_string().contains("line 2: unterminated"), "{}", e);
     }
 }
@@ -0,0 +1,222 @@
+/// A part of a term, either some text or a reference to a variable
+#[derive(Debug, PartialEq)]
+enum Piece<'a> {
+    Text(&'a str),
+    /// `name` is the variable with its `$`, e.g. "$mode" for both `$mode` and `${mode}`,
+    /// `raw` is how it is written in the term
+    Var {
+        name: String,
+        raw: &'a str,
+    },
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+/// split a term into text and variables, `$$` is kept as text (see `unescape`)
+fn parse(term: &str) -> Vec<Piece<'_>> {
+    let mut res = Vec::new();
+    let mut text_start = 0;
+    let mut i = 0;
+    while let Some(offset) = term[i..].find('$') {
+        let start = i + offset;
+        let rest = &term[start + 1..];
+        let (name, len) = if rest.starts_with('$') {
+            // escaped dollar, skipped
+            i = start + 2;
+            continue;
+        } else if let Some(braced) = rest.strip_prefix('{') {
+            match braced.find('}') {
+                Some(end) if end > 0 && braced[..end].chars().all(is_ident_char) => {
+                    (&braced[..end], end + 3)
+                }
+                _ => ("", 1),
+            }
+        } else {
+            let end = rest.find(|c| !is_ident_char(c)).unwrap_or(rest.len());
+            (&rest[..end], end + 1)
+        };
+        if name.is_empty() {
+            // a lone `$`, not a variable
+            i = start + 1;
+            continue;
+        }
+        if text_start < start {
+            res.push(Piece::Text(&term[text_start..start]));
+        }
+        res.push(Piece::Var {
+            name: format!("${}", name),
+            raw: &term[start..start + len],
+        });
+        i = start + len;
+        text_start = i;
+    }
+    if text_start < term.len() {
+        res.push(Piece::Text(&term[text_start..]));
+    }
+    res
+}
+
+/// return the variables (with their `$`) referenced in term
+pub fn vars_of(term: &str) -> Vec<String> {
+    parse(term)
+        .into_iter()
+        .filter_map(|piece| match piece {
+            Piece::Var { name, .. } => Some(name),
+            Piece::Text(_) => None,
+        })
+        .collect()
+}
+
+/// replace the variables of term for which lookup gives some terms, the others are kept as is
+/// a term made of a single variable is replaced by all its terms,
+/// a variable inside a larger term is replaced by its terms joined with spaces
+pub fn substitute<F>(term: &str, lookup: &mut F) -> Result<Vec<String>, String>
+where
+    F: FnMut(&str) -> Result<Option<Vec<String>>, String>,
+{
+    let pieces = parse(term);
+    if let [Piece::Var { name, raw }] = pieces.as_slice() {
+        return Ok(lookup(name)?.unwrap_or_else(|| vec![raw.to_string()]));
+    }
+    let mut res = String::new();
+    for piece in pieces {
+        match piece {
+            Piece::Text(text) => res.push_str(text),
+            Piece::Var { name, raw } => match lookup(&name)? {
+                Some(terms) => res.push_str(&terms.join(" ")),
+                None => res.push_str(raw),
+            },
+        }
+    }
+    Ok(vec![res])
+}
+
+/// `substitute` every term of cmd
+pub fn substitute_all<F>(cmd: &[String], mut lookup: F) -> Result<Vec<String>, String>
+where
+    F: FnMut(&str) -> Result<Option<Vec<String>>, String>,
+{
+    let mut res = Vec::new();
+    for term in cmd {
+        res.extend(substitute(term, &mut lookup)?);
+    }
+    Ok(res)
+}
+
+/// turn the escaped `$$` into `$`, once all the variables are filled
+pub fn unescape(term: &str) -> String {
+    term.replace("$$", "$")
+}
+
+/// replace each `${env:NAME}` of value with the value given by lookup for NAME, e.g. the one
+/// of the environment variable NAME, an error if it has none, `$$` is kept as is (see `unescape`)
+pub fn interpolate_env<F>(value: &str, lookup: &F) -> Result<String, String>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let mut res = String::new();
+    let mut rest = value;
+    while let Some(start) = rest.find('$') {
+        res.push_str(&rest[..start]);
+        rest = &rest[start..];
+        if rest.starts_with("$$") {
+            res.push_str("$$");
+            rest = &rest[2..];
+        } else if let Some(reference) = rest.strip_prefix("${env:") {
+            let Some(end) = reference.find('}') else {
+                return Err(format!("unclosed ${{env:...}} in {}", value));
+            };
+            let name = &reference[..end];
+            match lookup(name) {
+                Some(env_value) => res.push_str(&env_value),
+                None => {
+                    return Err(format!(
+                        "environment variable {} is not set, it is used in {}",
+                        name, value
+                    ))
+                }
+            }
+            rest = &reference[end + 1..];
+        } else {
+            res.push('$');
+            rest = &rest[1..];
+        }
+    }
+    res.push_str(rest);
+    Ok(res)
+}
+
+/// `interpolate_env` every string of table, in the nested arrays and tables too,
+/// with the environment of zexp
+pub fn interpolate_env_table(table: &mut toml::Table) -> Result<(), String> {
+    fn interpolate(value: &mut toml::Value) -> Result<(), String> {
+        match value {
+            toml::Value::String(string) => {
+                *string = interpolate_env(string, &|name| std::env::var(name).ok())?;
+            }
+            toml::Value::Array(values) => {
+                for value in values {
+                    interpolate(value)?;
+                }
+            }
+            toml::Value::Table(table) => interpolate_env_table(table)?,
+            _ => {}
+        }
+        Ok(())
+    }
+    for (_, value) in table.iter_mut() {
+        interpolate(value)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn lookup(var: &str) -> Result<Option<Vec<String>>, String> {
+        match var {
+            "$mode" => Ok(Some(vec!["fast".to_string()])),
+            "$opts" => Ok(Some(vec!["-a".to_string(), "-b".to_string()])),
+            _ => Ok(None),
+        }
+    }
+
+    #[test]
+    fn test_interpolate_env() {
+        let env = |name: &str| (name == "HOME").then(|| "/home/me".to_string());
+        let interpolate = |value: &str| interpolate_env(value, &env);
+        assert_eq!(interpolate("${env:HOME}/otawa").unwrap(), "/home/me/otawa");
+        // the variables of zexp are kept
+        assert_eq!(
+            interpolate("$app ${mode} $${env:HOME}").unwrap(),
+            "$app ${mode} $${env:HOME}"
+        );
+        let err = interpolate("${env:OTAWA_HOME}/bin").unwrap_err();
+        assert!(err.contains("environment variable OTAWA_HOME is not set"));
+        assert!(interpolate("${env:HOME").is_err());
+    }
+
+    #[test]
+    fn test_substitute() {
+        let subst = |term: &str| substitute(term, &mut lookup).unwrap();
+        assert_eq!(subst("$mode"), ["fast"]);
+        assert_eq!(subst("${mode}"), ["fast"]);
+        assert_eq!(subst("$opts"), ["-a", "-b"]);
+        assert_eq!(subst("--out=$mode.xml"), ["--out=fast.xml"]);
+        assert_eq!(subst("prefix_${mode}_suffix"), ["prefix_fast_suffix"]);
+        assert_eq!(subst("prefix_$mode_suffix"), ["prefix_$mode_suffix"]);
+        assert_eq!(subst("--opts=$opts"), ["--opts=-a -b"]);
+        assert_eq!(subst("$unknown/$mode"), ["$unknown/fast"]);
+        assert_eq!(subst("cost$$mode"), ["cost$$mode"]);
+        assert_eq!(unescape(&subst("cost$$mode")[0]), "cost$mode");
+        assert_eq!(subst("100$"), ["100$"]);
+    }
+
+    #[test]
+    fn test_vars_of() {
+        assert_eq!(vars_of("--out=${a}_$b.$$c $"), ["$a", "$b"]);
+    }
+}